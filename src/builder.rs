@@ -0,0 +1,488 @@
+// Copyright 2024 Koutheir Attouchi.
+// See the "LICENSE.txt" file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license. This file may not be copied, modified,
+// or distributed except according to those terms.
+
+#[cfg(test)]
+mod tests;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::utils::{resolve_in_root, FileVersion};
+use crate::{
+    glibc_ld_so_cache_1dot1, ld_elf_so_hints, ld_so_1dot7, ld_so_hints, AnyCache, Cache, CacheFile,
+    CacheFormat, Error, LoadStrategy, Result,
+};
+
+#[derive(Debug, Clone)]
+struct FormatOptions {
+    format: CacheFormat,
+    enabled: bool,
+    paths: Vec<PathBuf>,
+}
+
+/// Builder of a [`Cache`], selecting the dynamic loader caches to load.
+///
+/// By default, the builder loads all supported cache formats from their usual paths,
+/// in the order of precedence of the operating system this crate was compiled for,
+/// and skips cache files that are missing or invalid.
+/// This is what [`Cache::load()`] does.
+///
+/// A cache file configured for many formats (*e.g.*, `/etc/ld.so.cache`) is loaded by the first
+/// format that succeeds, in the order of precedence.
+#[derive(Debug, Clone)]
+pub struct CacheBuilder {
+    formats: Vec<FormatOptions>,
+    skip_missing_files: bool,
+    skip_invalid_files: bool,
+    root: Option<PathBuf>,
+    prefix_root: bool,
+    strategy: LoadStrategy,
+}
+
+impl Default for CacheBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CacheBuilder {
+    /// Create a builder with the default configuration.
+    #[must_use]
+    pub fn new() -> Self {
+        let precedence = if cfg!(target_os = "freebsd") {
+            [
+                CacheFormat::LdELFSOHints,
+                CacheFormat::LdSOHints,
+                CacheFormat::LdSO1dot7,
+                CacheFormat::GLibCLdSOCache1dot1,
+            ]
+        } else if cfg!(any(target_os = "openbsd", target_os = "netbsd")) {
+            [
+                CacheFormat::LdSOHints,
+                CacheFormat::LdELFSOHints,
+                CacheFormat::LdSO1dot7,
+                CacheFormat::GLibCLdSOCache1dot1,
+            ]
+        } else {
+            [
+                CacheFormat::GLibCLdSOCache1dot1,
+                CacheFormat::LdELFSOHints,
+                CacheFormat::LdSOHints,
+                CacheFormat::LdSO1dot7,
+            ]
+        };
+
+        let formats = precedence
+            .into_iter()
+            .map(|format| FormatOptions {
+                format,
+                enabled: true,
+                paths: default_paths(format),
+            })
+            .collect();
+
+        Self {
+            formats,
+            skip_missing_files: true,
+            skip_invalid_files: true,
+            root: None,
+            prefix_root: false,
+            strategy: LoadStrategy::default(),
+        }
+    }
+
+    /// Enable loading caches in the specified format.
+    #[must_use]
+    pub fn enable(mut self, format: CacheFormat) -> Self {
+        self.format_options(format).enabled = true;
+        self
+    }
+
+    /// Disable loading caches in the specified format.
+    #[must_use]
+    pub fn disable(mut self, format: CacheFormat) -> Self {
+        self.format_options(format).enabled = false;
+        self
+    }
+
+    /// Add a cache file to load in the specified format, after the files already configured
+    /// for that format.
+    #[must_use]
+    pub fn add_path(mut self, format: CacheFormat, path: impl Into<PathBuf>) -> Self {
+        self.format_options(format).paths.push(path.into());
+        self
+    }
+
+    /// Replace the cache files to load in the specified format.
+    ///
+    /// Passing no paths prevents loading the usual cache files of the format.
+    #[must_use]
+    pub fn paths(
+        mut self,
+        format: CacheFormat,
+        paths: impl IntoIterator<Item = impl Into<PathBuf>>,
+    ) -> Self {
+        self.format_options(format).paths = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the order in which formats are loaded, and their entries are returned.
+    ///
+    /// The specified formats take precedence, in the specified order, over the formats that
+    /// are not specified, which keep their previous relative order.
+    #[must_use]
+    pub fn precedence(mut self, formats: impl IntoIterator<Item = CacheFormat>) -> Self {
+        let mut index = 0_usize;
+        for format in formats {
+            if let Some(position) = self.formats[index..]
+                .iter()
+                .position(|options| options.format == format)
+            {
+                let options = self.formats.remove(index.saturating_add(position));
+                self.formats.insert(index, options);
+                index = index.saturating_add(1);
+            }
+        }
+        self
+    }
+
+    /// Set whether cache files that do not exist are skipped, instead of failing the load.
+    ///
+    /// This is `true` by default.
+    #[must_use]
+    pub fn skip_missing_files(mut self, skip: bool) -> Self {
+        self.skip_missing_files = skip;
+        self
+    }
+
+    /// Set whether cache files that cannot be read or parsed are skipped,
+    /// instead of failing the load.
+    ///
+    /// This is `true` by default.
+    #[must_use]
+    pub fn skip_invalid_files(mut self, skip: bool) -> Self {
+        self.skip_invalid_files = skip;
+        self
+    }
+
+    /// Set the root directory of the system whose caches are loaded, similarly to `chroot`.
+    ///
+    /// The configured cache files, the directories scanned by `ld-elf.so.hints` caches,
+    /// and the symbolic links they traverse, are then resolved under `root`.
+    /// The paths of entries remain relative to that system, unless [`Self::prefix_root()`]
+    /// is enabled.
+    #[must_use]
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Set whether the paths of entries are resolved under the root directory set by
+    /// [`Self::root()`], including the symbolic links they traverse,
+    /// making them accessible from the host.
+    ///
+    /// This is `false` by default.
+    #[must_use]
+    pub fn prefix_root(mut self, prefix: bool) -> Self {
+        self.prefix_root = prefix;
+        self
+    }
+
+    /// Set the strategy of loading cache files in memory.
+    ///
+    /// This is [`LoadStrategy::Map`] by default.
+    #[must_use]
+    pub fn strategy(mut self, strategy: LoadStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Load the configured dynamic loader caches.
+    ///
+    /// If a cache file cannot be loaded by any of the formats it is configured for,
+    /// and such failures are not skipped, then the first failure is returned.
+    pub fn load(&self) -> Result<Cache> {
+        self.check_failures(self.load_with_report())
+    }
+
+    /// Load the configured dynamic loader caches, and report the outcome of loading each
+    /// cache file.
+    ///
+    /// Failures are reported instead of being skipped or returned, regardless of
+    /// [`Self::skip_missing_files()`] and [`Self::skip_invalid_files()`].
+    /// A cache file already loaded by a format of higher precedence is not reported again.
+    #[must_use]
+    pub fn load_with_report(&self) -> (Cache, Vec<LoadReport>) {
+        self.load_impl(None)
+    }
+
+    /// Load the configured dynamic loader caches, sharing the caches of `previous` whose files
+    /// did not change.
+    pub(crate) fn reload(&self, previous: &Cache) -> Result<Cache> {
+        self.check_failures(self.load_impl(Some(previous)))
+    }
+
+    fn check_failures(&self, (cache, reports): (Cache, Vec<LoadReport>)) -> Result<Cache> {
+        let loaded: Vec<_> = reports
+            .iter()
+            .filter(|report| report.outcome == LoadOutcome::Loaded)
+            .map(|report| report.path.clone())
+            .collect();
+
+        let failure = reports.into_iter().find(|report| {
+            let skip = match report.outcome {
+                LoadOutcome::Loaded => true,
+                LoadOutcome::NotFound => self.skip_missing_files,
+                _ => self.skip_invalid_files,
+            };
+            !skip && !loaded.contains(&report.path)
+        });
+
+        match failure.and_then(|report| report.error) {
+            Some(err) => Err(err),
+            None => Ok(cache),
+        }
+    }
+
+    fn load_impl(&self, previous: Option<&Cache>) -> (Cache, Vec<LoadReport>) {
+        let mut files = Vec::<CacheFile>::default();
+        let mut reports = Vec::default();
+
+        for options in self.formats.iter().filter(|options| options.enabled) {
+            for path in &options.paths {
+                let host_path = match &self.root {
+                    Some(root) => resolve_in_root(root, path),
+                    None => path.clone(),
+                };
+
+                let is_loaded = files.iter().any(|file| {
+                    file.cache
+                        .as_ref()
+                        .is_some_and(|cache| cache.path() == host_path)
+                });
+                if is_loaded {
+                    continue;
+                }
+
+                // The version is retrieved before loading the file, so that a file replaced
+                // while it is loaded is considered as changed, and is loaded again later.
+                let version = FileVersion::of_path(&host_path);
+
+                let unchanged_cache = previous
+                    .and_then(|previous| {
+                        previous.files.iter().find(|file| {
+                            file.format == options.format
+                                && file.path == host_path
+                                && file.version.is_some()
+                                && file.version == version
+                        })
+                    })
+                    .and_then(|file| file.cache.clone());
+
+                let result = match unchanged_cache {
+                    Some(cache) => Ok(cache),
+
+                    None => load_cache(
+                        options.format,
+                        &host_path,
+                        path,
+                        self.root.as_deref(),
+                        self.strategy,
+                    )
+                    .map(Arc::new),
+                };
+
+                let (format, outcome, error, cache) = match result {
+                    Ok(cache) => (cache.format(), LoadOutcome::Loaded, None, Some(cache)),
+                    Err(err) => (
+                        options.format,
+                        LoadOutcome::from_error(&err),
+                        Some(err),
+                        None,
+                    ),
+                };
+
+                files.push(CacheFile {
+                    format: options.format,
+                    path: host_path.clone(),
+                    version,
+                    cache,
+                });
+
+                reports.push(LoadReport {
+                    format,
+                    path: host_path,
+                    outcome,
+                    error,
+                });
+            }
+        }
+
+        let cache = Cache {
+            files,
+            root: self.root.clone().filter(|_| self.prefix_root),
+            builder: self.clone(),
+            providers: Vec::default(),
+        };
+        (cache, reports)
+    }
+
+    fn format_options(&mut self, format: CacheFormat) -> &mut FormatOptions {
+        let index = match self.formats.iter().position(|o| o.format == format) {
+            Some(index) => index,
+
+            None => {
+                self.formats.push(FormatOptions {
+                    format,
+                    enabled: false,
+                    paths: Vec::default(),
+                });
+                self.formats.len().saturating_sub(1)
+            }
+        };
+        &mut self.formats[index]
+    }
+}
+
+/// Outcome of loading a cache file, reported by [`CacheBuilder::load_with_report()`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct LoadReport {
+    /// Format in which the cache file was loaded, or was configured to be loaded if loading failed.
+    ///
+    /// This differs from the configured format when the `glibc-ld.so.cache1.1` cache embedded in
+    /// a cache file in the `ld.so-1.7.0` format was loaded instead.
+    pub format: CacheFormat,
+    /// Path of the cache file.
+    pub path: PathBuf,
+    /// Outcome of loading the cache file.
+    pub outcome: LoadOutcome,
+    /// Failure of loading the cache file, if it was not loaded.
+    pub error: Option<Error>,
+}
+
+/// Outcome of loading a cache file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LoadOutcome {
+    /// The cache file was loaded.
+    Loaded,
+    /// The cache file does not exist.
+    NotFound,
+    /// The cache file could not be accessed due to insufficient permissions.
+    PermissionDenied,
+    /// The cache file does not start with a magic value of the format.
+    MagicIsUnsupported,
+    /// The cache file is smaller than the size declared by its header.
+    Truncated,
+    /// The cache file changed while it was loaded.
+    /// See [`LoadStrategy::MapVerified`].
+    Changed,
+    /// The cache file is not valid in the format.
+    ///
+    /// `offset` is the position in the file where parsing failed, when it is known.
+    ParseFailed {
+        /// Offset of the invalid data, in bytes.
+        offset: Option<usize>,
+    },
+    /// The cache file could not be read.
+    ReadFailed,
+}
+
+impl LoadOutcome {
+    fn from_error(err: &Error) -> Self {
+        match err {
+            Error::Open { source, .. }
+            | Error::Read { source, .. }
+            | Error::MapFile { source, .. }
+            | Error::ReadMetaData { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => Self::NotFound,
+                io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+                _ => Self::ReadFailed,
+            },
+
+            Error::MagicIsUnsupported { .. } => Self::MagicIsUnsupported,
+
+            Error::FileIsTruncated { .. } => Self::Truncated,
+
+            Error::FileChanged { .. } => Self::Changed,
+
+            Error::FileIsEmpty { .. } => Self::ParseFailed { offset: Some(0) },
+
+            Error::FileIsTooLarge { .. } => Self::ReadFailed,
+
+            Error::Parse { source, .. } => {
+                let offset = match source {
+                    nom::Err::Error(err) | nom::Err::Failure(err) => Some(err.input),
+                    nom::Err::Incomplete(_) => None,
+                };
+                Self::ParseFailed { offset }
+            }
+
+            _ => Self::ParseFailed { offset: None },
+        }
+    }
+}
+
+fn default_paths(format: CacheFormat) -> Vec<PathBuf> {
+    let paths = match format {
+        CacheFormat::GLibCLdSOCache1dot1 => vec![glibc_ld_so_cache_1dot1::CACHE_FILE_PATH],
+        CacheFormat::LdSO1dot7 => vec![ld_so_1dot7::CACHE_FILE_PATH],
+        CacheFormat::LdELFSOHints => vec![
+            ld_elf_so_hints::CACHE_FILE_PATH,
+            ld_elf_so_hints::CACHE_FILE_PATH_32,
+        ],
+        CacheFormat::LdSOHints => vec![ld_so_hints::CACHE_FILE_PATH],
+        CacheFormat::Custom => vec![],
+    };
+    paths.into_iter().map(PathBuf::from).collect()
+}
+
+/// Load the cache file at `path` on the host, which is at `target_path` in the system whose root
+/// directory is `root`.
+fn load_cache(
+    format: CacheFormat,
+    path: &Path,
+    target_path: &Path,
+    root: Option<&Path>,
+    strategy: LoadStrategy,
+) -> Result<AnyCache> {
+    match format {
+        CacheFormat::GLibCLdSOCache1dot1 => {
+            glibc_ld_so_cache_1dot1::Cache::load_with_strategy(path, strategy)
+                .map(AnyCache::GLibCLdSOCache1dot1)
+        }
+
+        // Both formats of the GNU/Linux dynamic loader use the same cache file, and the newer
+        // format can be embedded in a file in the old format. Similarly to the GNU/Linux
+        // dynamic loader, the newer format is preferred when it is present.
+        // An invalid embedded cache makes the file invalid, instead of hiding the failure behind
+        // the entries of the old format.
+        CacheFormat::LdSO1dot7 => {
+            let cache = ld_so_1dot7::Cache::load_with_strategy(path, strategy)?;
+            Ok(match cache.new_format()? {
+                Some(new_cache) => AnyCache::GLibCLdSOCache1dot1(new_cache),
+                None => AnyCache::LdSO1dot7(cache),
+            })
+        }
+
+        CacheFormat::LdELFSOHints => {
+            let abi = ld_elf_so_hints::abi_of_path(target_path);
+            let cache = ld_elf_so_hints::Cache::load_impl(path, abi, strategy)?;
+            Ok(AnyCache::LdELFSOHints(match root {
+                Some(root) => cache.with_root(root),
+                None => cache,
+            }))
+        }
+
+        CacheFormat::LdSOHints => {
+            ld_so_hints::Cache::load_with_strategy(path, strategy).map(AnyCache::LdSOHints)
+        }
+
+        CacheFormat::Custom => Err(Error::MagicIsUnsupported { path: path.into() }),
+    }
+}
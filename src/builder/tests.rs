@@ -0,0 +1,417 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use assert_matches::assert_matches;
+
+use super::{CacheBuilder, LoadOutcome};
+use crate::{AnyCache, CacheFormat, Error, LoadStrategy};
+
+const FORMATS: [CacheFormat; 4] = [
+    CacheFormat::GLibCLdSOCache1dot1,
+    CacheFormat::LdSO1dot7,
+    CacheFormat::LdELFSOHints,
+    CacheFormat::LdSOHints,
+];
+
+/// Return a builder that loads no cache files.
+fn empty_builder() -> CacheBuilder {
+    FORMATS
+        .into_iter()
+        .fold(CacheBuilder::new(), |builder, format| {
+            builder.paths(format, Vec::<PathBuf>::default())
+        })
+}
+
+fn loaded_formats(builder: &CacheBuilder) -> Vec<CacheFormat> {
+    builder
+        .load()
+        .unwrap()
+        .caches()
+        .map(AnyCache::format)
+        .collect()
+}
+
+#[test]
+fn default_precedence() {
+    let formats: Vec<_> = CacheBuilder::new()
+        .formats
+        .iter()
+        .map(|options| options.format)
+        .collect();
+
+    if cfg!(target_os = "freebsd") {
+        assert_eq!(formats[0], CacheFormat::LdELFSOHints);
+    } else if cfg!(any(target_os = "openbsd", target_os = "netbsd")) {
+        assert_eq!(formats[0], CacheFormat::LdSOHints);
+    } else {
+        assert_eq!(formats[0], CacheFormat::GLibCLdSOCache1dot1);
+    }
+    assert_eq!(formats.len(), FORMATS.len());
+}
+
+#[test]
+fn precedence() {
+    let builder = empty_builder()
+        .add_path(CacheFormat::LdSOHints, "tests/ld.so.hints/ld.so.hints")
+        .add_path(
+            CacheFormat::LdELFSOHints,
+            "tests/ld-elf.so.hints/ld-elf.so.hints",
+        )
+        .add_path(
+            CacheFormat::LdELFSOHints,
+            "tests/ld-elf.so.hints/ld-elf32.so.hints",
+        )
+        .add_path(
+            CacheFormat::GLibCLdSOCache1dot1,
+            "tests/glibc-ld.so.cache1.1/ld.so.cache",
+        );
+
+    let builder = builder.precedence([CacheFormat::LdELFSOHints, CacheFormat::LdSOHints]);
+    assert_eq!(
+        loaded_formats(&builder),
+        [
+            CacheFormat::LdELFSOHints,
+            CacheFormat::LdELFSOHints,
+            CacheFormat::LdSOHints,
+            CacheFormat::GLibCLdSOCache1dot1,
+        ]
+    );
+
+    let builder = builder.precedence([CacheFormat::GLibCLdSOCache1dot1]);
+    assert_eq!(
+        loaded_formats(&builder),
+        [
+            CacheFormat::GLibCLdSOCache1dot1,
+            CacheFormat::LdELFSOHints,
+            CacheFormat::LdELFSOHints,
+            CacheFormat::LdSOHints,
+        ]
+    );
+
+    let builder = builder.disable(CacheFormat::LdELFSOHints);
+    assert_eq!(
+        loaded_formats(&builder),
+        [CacheFormat::GLibCLdSOCache1dot1, CacheFormat::LdSOHints]
+    );
+
+    let builder = builder.enable(CacheFormat::LdELFSOHints);
+    assert_eq!(loaded_formats(&builder).len(), 4);
+}
+
+#[test]
+fn shared_cache_file() {
+    // The compatibility-mode file is loaded once, in the newer format.
+    let path = "tests/ld.so-1.7.0/ld.so.cache.compat";
+    for precedence in [CacheFormat::GLibCLdSOCache1dot1, CacheFormat::LdSO1dot7] {
+        let builder = empty_builder()
+            .add_path(CacheFormat::GLibCLdSOCache1dot1, path)
+            .add_path(CacheFormat::LdSO1dot7, path)
+            .precedence([precedence])
+            .skip_invalid_files(false);
+
+        assert_eq!(loaded_formats(&builder), [CacheFormat::GLibCLdSOCache1dot1]);
+    }
+
+    // A file in the old format only is not an error, as long as a format loads it.
+    let path = "tests/ld.so-1.7.0/ld.so.cache";
+    let builder = empty_builder()
+        .add_path(CacheFormat::GLibCLdSOCache1dot1, path)
+        .add_path(CacheFormat::LdSO1dot7, path)
+        .skip_invalid_files(false);
+
+    assert_eq!(loaded_formats(&builder), [CacheFormat::LdSO1dot7]);
+}
+
+#[test]
+fn missing_files() {
+    let builder = empty_builder().add_path(CacheFormat::LdSOHints, "tests/missing");
+    assert!(loaded_formats(&builder).is_empty());
+
+    let builder = builder.skip_missing_files(false);
+    assert_matches!(builder.load(), Err(Error::Open { .. }));
+
+    let builder = builder.skip_invalid_files(false).skip_missing_files(true);
+    assert!(loaded_formats(&builder).is_empty());
+}
+
+#[test]
+fn invalid_files() {
+    let builder = empty_builder().add_path(CacheFormat::LdSOHints, "README.md");
+    assert!(loaded_formats(&builder).is_empty());
+
+    let builder = builder.skip_invalid_files(false);
+    assert_matches!(builder.load(), Err(Error::MagicIsUnsupported { .. }));
+
+    let builder = builder.skip_invalid_files(true).skip_missing_files(false);
+    assert!(loaded_formats(&builder).is_empty());
+}
+
+#[test]
+fn root() {
+    let builder = CacheBuilder::new()
+        .root("tests/root")
+        .skip_invalid_files(false);
+    let cache = builder.load().unwrap();
+    assert_eq!(cache.caches().count(), 2);
+    assert!(cache
+        .iter()
+        .unwrap()
+        .map(Result::unwrap)
+        .all(|entry| entry.full_path.starts_with("/")));
+
+    let entry = cache.lookup_first("libc.so.7").unwrap().unwrap();
+    assert_eq!(entry.full_path, Path::new("/lib/libc.so.7"));
+
+    let cache = builder.prefix_root(true).load().unwrap();
+    let entry = cache.lookup_first("libc.so.7").unwrap().unwrap();
+    assert_eq!(
+        entry.full_path,
+        Path::new("tests/root/fixture/lib/libc.so.7")
+    );
+    assert!(entry.full_path.is_file());
+    assert!(cache
+        .iter()
+        .unwrap()
+        .map(Result::unwrap)
+        .all(|entry| entry.full_path.starts_with("tests/root")));
+}
+
+#[test]
+fn entry_sources() {
+    let glibc_path = Path::new("tests/glibc-ld.so.cache1.1/ld.so.cache");
+    let ld_elf_path = Path::new("tests/ld-elf.so.hints/ld-elf.so.hints-scan");
+    let ld_so_path = Path::new("tests/ld.so-1.7.0/ld.so.cache");
+    let builder = empty_builder()
+        .add_path(CacheFormat::GLibCLdSOCache1dot1, glibc_path)
+        .add_path(CacheFormat::LdELFSOHints, ld_elf_path)
+        .add_path(CacheFormat::LdSO1dot7, ld_so_path)
+        .skip_invalid_files(false);
+    let cache = builder.load().unwrap();
+
+    let mut formats = Vec::default();
+    for entry in cache.iter().unwrap().map(Result::unwrap) {
+        let expected_path = match entry.cache_format {
+            CacheFormat::GLibCLdSOCache1dot1 => glibc_path,
+            CacheFormat::LdELFSOHints => ld_elf_path,
+            CacheFormat::LdSO1dot7 => ld_so_path,
+            format => panic!("unexpected format: {format:?}"),
+        };
+        assert_eq!(entry.cache_path, expected_path);
+
+        if formats.last() != Some(&entry.cache_format) {
+            formats.push(entry.cache_format);
+        }
+    }
+    assert_eq!(formats, loaded_formats(&builder));
+
+    let entry = cache.lookup_first("libc.so.7").unwrap().unwrap();
+    assert_eq!(entry.cache_format, CacheFormat::LdELFSOHints);
+    assert_eq!(entry.cache_path, ld_elf_path);
+}
+
+#[test]
+fn load_with_report() {
+    let builder = empty_builder()
+        .add_path(
+            CacheFormat::GLibCLdSOCache1dot1,
+            "tests/glibc-ld.so.cache1.1/ld.so.cache",
+        )
+        .add_path(
+            CacheFormat::GLibCLdSOCache1dot1,
+            "tests/glibc-ld.so.cache1.1/ld.so.cache-truncated",
+        )
+        .add_path(
+            CacheFormat::GLibCLdSOCache1dot1,
+            "tests/ld.so-1.7.0/ld.so.cache",
+        )
+        .add_path(CacheFormat::LdSO1dot7, "tests/ld.so-1.7.0/ld.so.cache")
+        .add_path(
+            CacheFormat::LdSO1dot7,
+            "tests/glibc-ld.so.cache1.1/ld.so.cache",
+        )
+        .add_path(CacheFormat::LdSOHints, "tests/missing")
+        .precedence([CacheFormat::GLibCLdSOCache1dot1, CacheFormat::LdSO1dot7]);
+
+    let (cache, reports) = builder.load_with_report();
+    assert_eq!(cache.caches().count(), 2);
+
+    let outcomes: Vec<_> = reports
+        .iter()
+        .map(|report| (report.format, report.path.to_str().unwrap(), report.outcome))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            (
+                CacheFormat::GLibCLdSOCache1dot1,
+                "tests/glibc-ld.so.cache1.1/ld.so.cache",
+                LoadOutcome::Loaded
+            ),
+            (
+                CacheFormat::GLibCLdSOCache1dot1,
+                "tests/glibc-ld.so.cache1.1/ld.so.cache-truncated",
+                LoadOutcome::Truncated
+            ),
+            (
+                CacheFormat::GLibCLdSOCache1dot1,
+                "tests/ld.so-1.7.0/ld.so.cache",
+                LoadOutcome::MagicIsUnsupported
+            ),
+            (
+                CacheFormat::LdSO1dot7,
+                "tests/ld.so-1.7.0/ld.so.cache",
+                LoadOutcome::Loaded
+            ),
+            (
+                CacheFormat::LdSOHints,
+                "tests/missing",
+                LoadOutcome::NotFound
+            ),
+        ]
+    );
+
+    for report in &reports {
+        assert_eq!(
+            report.error.is_none(),
+            report.outcome == LoadOutcome::Loaded
+        );
+    }
+
+    // Failures are reported as they are returned by `CacheBuilder::load()`.
+    let builder = builder.skip_invalid_files(false);
+    assert_matches!(builder.load(), Err(Error::FileIsTruncated { size: 40, .. }));
+}
+
+#[test]
+fn strategy() {
+    let path = "tests/ld.so-1.7.0/ld.so.cache.compat";
+    for strategy in [
+        LoadStrategy::Map,
+        LoadStrategy::MapVerified,
+        LoadStrategy::Read,
+    ] {
+        let builder = empty_builder()
+            .add_path(CacheFormat::LdSO1dot7, path)
+            .add_path(
+                CacheFormat::LdSOHints,
+                "tests/glibc-ld.so.cache1.1/ld.so.cache-truncated",
+            )
+            .precedence([CacheFormat::LdSO1dot7])
+            .strategy(strategy);
+
+        let (cache, reports) = builder.load_with_report();
+        assert_eq!(reports[0].outcome, LoadOutcome::Loaded);
+        assert_eq!(reports[1].outcome, LoadOutcome::MagicIsUnsupported);
+        assert!(cache.iter().unwrap().all(|entry| entry.is_ok()));
+
+        let builder = builder.paths(
+            CacheFormat::GLibCLdSOCache1dot1,
+            ["tests/glibc-ld.so.cache1.1/ld.so.cache-truncated"],
+        );
+        assert_matches!(
+            builder.skip_invalid_files(false).load(),
+            Err(Error::FileIsTruncated { .. })
+        );
+    }
+}
+
+#[test]
+fn reload() {
+    let dir = std::env::temp_dir().join(format!(
+        "dynamic-loader-cache-reload-{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+
+    let glibc_path = dir.join("ld.so.cache");
+    let hints_path = dir.join("ld.so.hints");
+    fs::copy("tests/glibc-ld.so.cache1.1/ld.so.cache", &glibc_path).unwrap();
+    fs::copy("tests/ld.so.hints/ld.so.hints", &hints_path).unwrap();
+
+    let builder = empty_builder()
+        .add_path(CacheFormat::GLibCLdSOCache1dot1, &glibc_path)
+        .add_path(CacheFormat::LdSOHints, &hints_path);
+
+    let cache = builder.load().unwrap();
+    assert!(!cache.is_stale());
+
+    // Unchanged cache files are shared.
+    let reloaded = cache.reload().unwrap();
+    assert!(!reloaded.is_stale());
+    assert!(cache
+        .files
+        .iter()
+        .zip(&reloaded.files)
+        .all(|(old, new)| Arc::ptr_eq(old.cache.as_ref().unwrap(), new.cache.as_ref().unwrap())));
+
+    // Replace a cache file the way `ldconfig` does.
+    let entries: Vec<_> = cache.iter().unwrap().map(|e| e.unwrap()).collect();
+    let new_path = dir.join("ld.so.cache~");
+    fs::copy("tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps", &new_path).unwrap();
+    fs::rename(&new_path, &glibc_path).unwrap();
+    assert!(cache.is_stale());
+
+    let reloaded = cache.reload().unwrap();
+    assert!(!reloaded.is_stale());
+    assert!(!Arc::ptr_eq(
+        cache.files[0].cache.as_ref().unwrap(),
+        reloaded.files[0].cache.as_ref().unwrap()
+    ));
+    assert!(Arc::ptr_eq(
+        cache.files[1].cache.as_ref().unwrap(),
+        reloaded.files[1].cache.as_ref().unwrap()
+    ));
+
+    // Entries of the previous cache remain valid.
+    assert!(entries.iter().all(|entry| !entry.file_name.is_empty()));
+    assert_eq!(cache.iter().unwrap().count(), entries.len());
+
+    // Removing a cache file is also a change.
+    fs::remove_file(&hints_path).unwrap();
+    assert!(reloaded.is_stale());
+    let reloaded = reloaded.reload().unwrap();
+    assert_eq!(reloaded.caches().count(), 1);
+    assert!(!reloaded.is_stale());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compat_embedded_cache() {
+    let builder = empty_builder().add_path(
+        CacheFormat::LdSO1dot7,
+        "tests/ld.so-1.7.0/ld.so.cache.compat",
+    );
+    let (_, reports) = builder.load_with_report();
+    assert_eq!(reports[0].outcome, LoadOutcome::Loaded);
+    assert_eq!(reports[0].format, CacheFormat::GLibCLdSOCache1dot1);
+    assert_eq!(loaded_formats(&builder), [CacheFormat::GLibCLdSOCache1dot1]);
+
+    // The embedded cache is invalid, so the entries of the old format are not loaded instead.
+    let path = "tests/ld.so-1.7.0/ld.so.cache.compat-bad-glibc";
+    let builder = empty_builder()
+        .add_path(CacheFormat::LdSO1dot7, path)
+        .skip_invalid_files(false);
+    let (cache, reports) = builder.load_with_report();
+    assert_eq!(cache.caches().count(), 0);
+    assert_eq!(reports[0].format, CacheFormat::LdSO1dot7);
+    assert_matches!(reports[0].outcome, LoadOutcome::ParseFailed { .. });
+    assert_matches!(builder.load(), Err(Error::Parse { .. }));
+}
+
+#[test]
+fn load_with_report_offset() {
+    // The byte order of the `glibc-ld.so.cache1.1` cache embedded at offset 33304 is invalid.
+    let builder = empty_builder().add_path(
+        CacheFormat::LdSO1dot7,
+        "tests/ld.so-1.7.0/ld.so.cache.compat-bad-glibc",
+    );
+    let (_, reports) = builder.load_with_report();
+    assert_eq!(
+        reports[0].outcome,
+        LoadOutcome::ParseFailed {
+            offset: Some(33304)
+        }
+    );
+}
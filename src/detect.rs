@@ -0,0 +1,85 @@
+// Copyright 2024 Koutheir Attouchi.
+// See the "LICENSE.txt" file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license. This file may not be copied, modified,
+// or distributed except according to those terms.
+
+#[cfg(test)]
+mod tests;
+
+use std::path::Path;
+
+use crate::utils::map_file;
+use crate::{
+    glibc_ld_so_cache_1dot1, ld_elf_so_hints, ld_so_1dot7, ld_so_hints, ByteOrder, CacheFormat,
+    DataModel, Error, Result,
+};
+
+/// Format of a cache file, as detected by [`detect_format()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct DetectedFormat {
+    /// Format of the cache.
+    pub format: CacheFormat,
+    /// Byte order of the data in the cache.
+    pub byte_order: ByteOrder,
+    /// Data model of the cache, for formats whose layout depends on it, *i.e.*, `ld.so.hints`.
+    pub data_model: Option<DataModel>,
+    /// Whether an `ld.so-1.7.0` cache embeds a cache in the `glibc-ld.so.cache1.1` format,
+    /// as written by `ldconfig` in its compatibility mode.
+    pub embeds_new_format: bool,
+}
+
+impl DetectedFormat {
+    fn new(format: CacheFormat, byte_order: ByteOrder) -> Self {
+        Self {
+            format,
+            byte_order,
+            data_model: None,
+            embeds_new_format: false,
+        }
+    }
+}
+
+/// Return the format of the cache in `bytes`, or `None` if it is not in a supported format.
+///
+/// The format is detected by checking the magic values of the supported formats.
+/// The rest of the cache is not validated, so loading it might still fail,
+/// *e.g.*, with [`Error::FileIsTruncated`].
+#[must_use]
+pub fn detect_format(bytes: &[u8]) -> Option<DetectedFormat> {
+    if let Some((byte_order, embeds_new_format)) = ld_so_1dot7::Cache::detect(bytes) {
+        return Some(DetectedFormat {
+            embeds_new_format,
+            ..DetectedFormat::new(CacheFormat::LdSO1dot7, byte_order)
+        });
+    }
+
+    if let Some(byte_order) = glibc_ld_so_cache_1dot1::Cache::detect(bytes) {
+        return Some(DetectedFormat::new(
+            CacheFormat::GLibCLdSOCache1dot1,
+            byte_order,
+        ));
+    }
+
+    if let Some((data_model, byte_order)) = ld_so_hints::Cache::detect(bytes) {
+        return Some(DetectedFormat {
+            data_model: Some(data_model),
+            ..DetectedFormat::new(CacheFormat::LdSOHints, byte_order)
+        });
+    }
+
+    ld_elf_so_hints::Cache::detect(bytes)
+        .map(|byte_order| DetectedFormat::new(CacheFormat::LdELFSOHints, byte_order))
+}
+
+/// Return the format of the cache file at `path`, or `None` if it is not in a supported format.
+///
+/// See [`detect_format()`].
+pub fn detect_format_of(path: impl AsRef<Path>) -> Result<Option<DetectedFormat>> {
+    match map_file(path.as_ref()) {
+        Ok(map) => Ok(detect_format(&map)),
+        Err(Error::FileIsEmpty { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
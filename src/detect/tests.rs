@@ -0,0 +1,153 @@
+use assert_matches::assert_matches;
+
+use super::{detect_format, detect_format_of, DetectedFormat};
+use crate::{AnyCache, ByteOrder, CacheFormat, DataModel, Error};
+
+fn detected(path: &str) -> DetectedFormat {
+    detect_format_of(path).unwrap().unwrap()
+}
+
+#[test]
+fn glibc_ld_so_cache_1dot1() {
+    let format = detected("tests/glibc-ld.so.cache1.1/ld.so.cache");
+    assert_eq!(format.format, CacheFormat::GLibCLdSOCache1dot1);
+    assert_eq!(format.byte_order, ByteOrder::LittleEndian);
+    assert_eq!(format.data_model, None);
+    assert!(!format.embeds_new_format);
+}
+
+#[test]
+fn ld_so_1dot7() {
+    let format = detected("tests/ld.so-1.7.0/ld.so.cache");
+    assert_eq!(format.format, CacheFormat::LdSO1dot7);
+    assert_eq!(format.byte_order, ByteOrder::LittleEndian);
+    assert!(!format.embeds_new_format);
+
+    for (name, byte_order) in [
+        ("ld.so.cache.compat", ByteOrder::LittleEndian),
+        ("ld.so.cache.compat-le32", ByteOrder::LittleEndian),
+        ("ld.so.cache.compat-le64", ByteOrder::LittleEndian),
+        ("ld.so.cache.compat-be32", ByteOrder::BigEndian),
+        ("ld.so.cache.compat-be64", ByteOrder::BigEndian),
+    ] {
+        let format = detected(&format!("tests/ld.so-1.7.0/{name}"));
+        assert_eq!(format.format, CacheFormat::LdSO1dot7);
+        assert_eq!(format.byte_order, byte_order);
+        assert!(format.embeds_new_format);
+    }
+}
+
+#[test]
+fn ld_so_hints() {
+    for (name, data_model, byte_order) in [
+        ("ld.so.hints", DataModel::LP64, ByteOrder::LittleEndian),
+        ("ld.so.hints-be32", DataModel::ILP32, ByteOrder::BigEndian),
+        ("ld.so.hints-be64", DataModel::LP64, ByteOrder::BigEndian),
+    ] {
+        let format = detected(&format!("tests/ld.so.hints/{name}"));
+        assert_eq!(format.format, CacheFormat::LdSOHints);
+        assert_eq!(format.byte_order, byte_order);
+        assert_eq!(format.data_model, Some(data_model));
+    }
+}
+
+#[test]
+fn ld_elf_so_hints() {
+    for name in [
+        "ld-elf.so.hints",
+        "ld-elf32.so.hints",
+        "ld-elf.so.hints-scan",
+    ] {
+        let format = detected(&format!("tests/ld-elf.so.hints/{name}"));
+        assert_eq!(format.format, CacheFormat::LdELFSOHints);
+        assert_eq!(format.byte_order, ByteOrder::LittleEndian);
+        assert_eq!(format.data_model, None);
+    }
+}
+
+#[test]
+fn unsupported() {
+    assert_eq!(detect_format(b""), None);
+    assert_eq!(detect_format(b"glibc-ld.so.cache1"), None);
+    assert_eq!(detect_format_of("README.md").unwrap(), None);
+    assert_matches!(detect_format_of("tests/missing"), Err(Error::Open { .. }));
+}
+
+#[test]
+fn any_cache() {
+    for (path, format) in [
+        (
+            "tests/glibc-ld.so.cache1.1/ld.so.cache",
+            CacheFormat::GLibCLdSOCache1dot1,
+        ),
+        ("tests/ld.so-1.7.0/ld.so.cache", CacheFormat::LdSO1dot7),
+        (
+            "tests/ld.so-1.7.0/ld.so.cache.compat-be64",
+            CacheFormat::LdSO1dot7,
+        ),
+        ("tests/ld.so.hints/ld.so.hints-be64", CacheFormat::LdSOHints),
+        (
+            "tests/ld-elf.so.hints/ld-elf.so.hints-scan",
+            CacheFormat::LdELFSOHints,
+        ),
+    ] {
+        let cache = AnyCache::load(path).unwrap();
+        assert_eq!(cache.format(), format);
+        assert_eq!(cache.path(), std::path::Path::new(path));
+
+        let entries: Vec<_> = cache.iter().unwrap().map(Result::unwrap).collect();
+        assert!(!entries.is_empty());
+
+        let entry = &entries[0];
+        assert!(cache
+            .lookup(&entry.file_name)
+            .unwrap()
+            .map(Result::unwrap)
+            .any(|e| e.full_path == entry.full_path));
+    }
+
+    assert_matches!(
+        AnyCache::load("README.md"),
+        Err(Error::MagicIsUnsupported { .. })
+    );
+}
+
+#[test]
+fn truncated() {
+    let path = "tests/glibc-ld.so.cache1.1/ld.so.cache-truncated";
+    assert_eq!(detected(path).format, CacheFormat::GLibCLdSOCache1dot1);
+    assert_matches!(AnyCache::load(path), Err(Error::FileIsTruncated { .. }));
+
+    let format = detect_format(b"glibc-ld.so.cache1.1").unwrap();
+    assert_eq!(format.format, CacheFormat::GLibCLdSOCache1dot1);
+    assert_matches!(
+        AnyCache::from_bytes(b"glibc-ld.so.cache1.1".to_vec()),
+        Err(Error::FileIsTruncated { .. })
+    );
+
+    let bytes = std::fs::read("tests/ld-elf.so.hints/ld-elf.so.hints").unwrap();
+    assert_eq!(
+        detect_format(&bytes[..8]).unwrap().format,
+        CacheFormat::LdELFSOHints
+    );
+    assert_matches!(
+        AnyCache::from_bytes(bytes[..8].to_vec()),
+        Err(Error::FileIsTruncated { .. })
+    );
+}
+
+#[test]
+fn any_cache_from_bytes() {
+    let path = "tests/ld.so.hints/ld.so.hints-be64";
+    let cache = AnyCache::from_bytes(std::fs::read(path).unwrap()).unwrap();
+    assert_eq!(cache.format(), CacheFormat::LdSOHints);
+    assert_eq!(
+        cache.iter().unwrap().count(),
+        AnyCache::load(path).unwrap().iter().unwrap().count()
+    );
+
+    assert_matches!(
+        AnyCache::from_bytes_with_path(Vec::new(), "memory"),
+        Err(Error::MagicIsUnsupported { path }) if path == std::path::Path::new("memory")
+    );
+}
@@ -6,6 +6,8 @@
 
 use std::path::PathBuf;
 
+use nom::error::ErrorKind;
+
 /// Information about a failure of an operation.
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -25,6 +27,13 @@ pub enum Error {
         source: std::io::Error,
     },
 
+    #[error("failed to read file. Path: {path}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
     #[error("failed to map file. Path: {path}")]
     MapFile {
         path: PathBuf,
@@ -42,6 +51,9 @@ pub enum Error {
     #[error("file is empty. Path: {path}")]
     FileIsEmpty { path: PathBuf },
 
+    #[error("file is too large. Path: {path}. Size: {size}")]
+    FileIsTooLarge { path: PathBuf, size: u64 },
+
     #[error("parsing failed. Path: {path}")]
     Parse {
         path: PathBuf,
@@ -49,9 +61,28 @@ pub enum Error {
         source: nom::Err<nom::error::Error<usize>>,
     },
 
+    #[error("file is truncated. Path: {path}. Size: {size}")]
+    FileIsTruncated { path: PathBuf, size: usize },
+
+    #[error("file changed while it was loaded. Path: {path}")]
+    FileChanged { path: PathBuf },
+
+    #[error("magic value is not supported. Path: {path}")]
+    MagicIsUnsupported { path: PathBuf },
+
     #[error("offset is invalid. Path: {path}")]
     OffsetIsInvalid { path: PathBuf },
 
+    #[error("ABI is not supported on this system. ABI: {abi:?}")]
+    AbiIsUnsupported { abi: crate::ElfClass },
+
+    #[cfg(all(feature = "watch", target_os = "linux"))]
+    #[error("failed to watch cache files")]
+    Watch {
+        #[source]
+        source: std::io::Error,
+    },
+
     #[error(transparent)]
     FromBytesWithNul(#[from] core::ffi::FromBytesWithNulError),
 
@@ -63,6 +94,29 @@ pub enum Error {
 }
 
 impl Error {
+    /// Return the failure of parsing the header of a cache, reporting caches that are smaller
+    /// than the size declared by their header as truncated.
+    pub(crate) fn from_nom_parse_header(
+        source: nom::Err<nom::error::Error<&[u8]>>,
+        bytes: &[u8],
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        match &source {
+            nom::Err::Error(err) | nom::Err::Failure(err)
+                if matches!(err.code, ErrorKind::Eof | ErrorKind::TooLarge) =>
+            {
+                Self::FileIsTruncated {
+                    path: path.into(),
+                    size: bytes.len(),
+                }
+            }
+
+            _ => Self::from_nom_parse(source, bytes, path),
+        }
+    }
+
+    /// Return the failure of parsing a cache, locating the invalid data by its offset in `bytes`,
+    /// which are all the bytes of the cache file.
     pub(crate) fn from_nom_parse(
         source: nom::Err<nom::error::Error<&[u8]>>,
         bytes: &[u8],
@@ -71,9 +125,18 @@ impl Error {
         Self::Parse {
             path: path.into(),
             source: source.map(|r| nom::error::Error {
-                input: bytes.len().saturating_sub(r.input.len()),
+                input: offset_in(bytes, r.input),
                 code: r.code,
             }),
         }
     }
 }
+
+/// Return the offset of `input` in `bytes`, assuming that `input` ends where `bytes` ends
+/// if it is not a part of `bytes`.
+fn offset_in(bytes: &[u8], input: &[u8]) -> usize {
+    (input.as_ptr() as usize)
+        .checked_sub(bytes.as_ptr() as usize)
+        .filter(|&offset| offset <= bytes.len())
+        .unwrap_or_else(|| bytes.len().saturating_sub(input.len()))
+}
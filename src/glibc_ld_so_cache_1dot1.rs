@@ -9,25 +9,33 @@
 #[cfg(test)]
 mod tests;
 
-use core::ffi::CStr;
+use core::cmp::Ordering;
+use core::ffi::{c_char, c_int, CStr};
 use core::iter::FusedIterator;
 use core::mem::{offset_of, size_of};
+use std::borrow::Cow;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use memmap2::Mmap;
 use nom::bytes::complete::{tag as nom_tag, take as nom_take};
 use nom::combinator::peek as nom_peek;
-use nom::number::complete::{u32 as nom_u32, u8 as nom_u8};
+use nom::multi::many0 as nom_many0;
+use nom::number::complete::{u32 as nom_u32, u64 as nom_u64, u8 as nom_u8};
 use nom::number::Endianness;
 use nom::sequence::{preceded as nom_preceded, terminated as nom_terminated, tuple as nom_tuple};
 use nom::IResult;
 
-use crate::utils::{cstr_entry_to_crate_entry, map_file};
-use crate::{CacheProvider, Error, Result};
+use crate::utils::{cow_os_str_from_cstr, cstr_entry_to_crate_entry, load_file, CacheBytes};
+use crate::{
+    ld_so_1dot7, ByteOrder, CacheFormat, CacheProvider, ElfClass, Error, LoadStrategy, Result,
+};
 
-static CACHE_FILE_PATH: &str = "/etc/ld.so.cache";
+pub(crate) static CACHE_FILE_PATH: &str = "/etc/ld.so.cache";
 
-static MAGIC: &[u8] = b"glibc-ld.so.cache1.1";
+pub(crate) static MAGIC: &[u8] = b"glibc-ld.so.cache1.1";
+
+const EXTENSION_MAGIC: u32 = 0xeaa4_2174;
 
 #[repr(C)]
 struct Header {
@@ -49,17 +57,375 @@ struct Entry {
     hw_cap: u64,
 }
 
+/// Tag of the extension section describing the program that generated the cache.
+const EXTENSION_TAG_GENERATOR: u32 = 0;
+
+/// Tag of the extension section listing the glibc-hwcaps subdirectories.
+const EXTENSION_TAG_GLIBC_HWCAPS: u32 = 1;
+
+#[repr(C)]
+struct ExtensionSectionHeader {
+    tag: u32,
+    flags: u32,
+    /// Offset of the section data, from the start of the file.
+    offset: u32,
+    /// Size of the section data, in bytes.
+    size: u32,
+}
+
+/// Mask of the bits of [`Flags`] that hold the type of the shared library.
+const FLAG_TYPE_MASK: u32 = 0x00ff;
+
+/// Mask of the bits of [`Flags`] that hold the architecture requirements of the shared library.
+const FLAG_REQUIRED_MASK: u32 = 0xff00;
+
+/// Bit of [`HwCap`] indicating that the lower 32 bits are an index into
+/// the glibc-hwcaps subdirectories table.
+const HWCAP_EXTENSION: u64 = 1_u64 << 62;
+
+/// Number of bits of [`HwCap`] holding the ISA level, starting at bit 32.
+const HWCAP_ISA_LEVEL_COUNT: u32 = 10;
+
+/// Mask of the ISA level bits of [`HwCap`], after shifting them down by 32 bits.
+const HWCAP_ISA_LEVEL_MASK: u32 = (1_u32 << HWCAP_ISA_LEVEL_COUNT) - 1;
+
+/// Information about a cache entry, specific to the `glibc-ld.so.cache1.1` format.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct EntryMetadata<'cache> {
+    /// Type and architecture of the shared library.
+    pub flags: Flags,
+    /// Minimum operating system version required by the shared library.
+    pub os_version: OsVersion,
+    /// Hardware capabilities required by the shared library.
+    pub hw_cap: HwCap,
+    /// Name of the glibc-hwcaps subdirectory containing the shared library, if any,
+    /// *e.g.*, `x86-64-v3`.
+    pub hwcaps_subdirectory: Option<Cow<'cache, OsStr>>,
+}
+
+impl EntryMetadata<'_> {
+    /// Return information that owns its data, and therefore does not borrow from the cache.
+    #[must_use]
+    pub fn into_owned(self) -> EntryMetadata<'static> {
+        EntryMetadata {
+            hwcaps_subdirectory: self
+                .hwcaps_subdirectory
+                .map(|name| Cow::Owned(name.into_owned())),
+            ..self
+        }
+    }
+}
+
+/// Flags recorded by `ldconfig` for a shared library.
+///
+/// These flags describe the type of the shared library, and the architecture it was built for.
+/// The dynamic loader only considers shared libraries whose flags are compatible with its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Flags(u32);
+
+impl Flags {
+    /// Create flags from their raw value, as stored in the cache.
+    #[must_use]
+    pub const fn from_raw(raw: u32) -> Self {
+        Self(raw)
+    }
+
+    /// Return the raw value of the flags, as stored in the cache.
+    #[must_use]
+    pub const fn raw(self) -> u32 {
+        self.0
+    }
+
+    /// Return the type of the shared library.
+    #[must_use]
+    pub const fn library_type(self) -> LibraryType {
+        LibraryType::from_raw((self.0 & FLAG_TYPE_MASK) as u8)
+    }
+
+    /// Return the architecture that the shared library was built for.
+    #[must_use]
+    pub const fn architecture(self) -> Architecture {
+        Architecture::from_raw(((self.0 & FLAG_REQUIRED_MASK) >> 8) as u8)
+    }
+}
+
+/// Type of a shared library, as recorded by `ldconfig`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum LibraryType {
+    /// `a.out` library linked against `libc.so.4` (`FLAG_LIBC4`).
+    Libc4,
+    /// ELF library (`FLAG_ELF`).
+    Elf,
+    /// ELF library linked against `libc.so.5` (`FLAG_ELF_LIBC5`).
+    ElfLibc5,
+    /// ELF library linked against `libc.so.6` (`FLAG_ELF_LIBC6`).
+    ElfLibc6,
+    /// Unrecognized library type.
+    Unknown(u8),
+}
+
+impl LibraryType {
+    const fn from_raw(raw: u8) -> Self {
+        match raw {
+            0x00 => Self::Libc4,
+            0x01 => Self::Elf,
+            0x02 => Self::ElfLibc5,
+            0x03 => Self::ElfLibc6,
+            _ => Self::Unknown(raw),
+        }
+    }
+}
+
+/// Architecture that a shared library was built for, as recorded by `ldconfig`.
+///
+/// `ldconfig` only records an architecture for shared libraries that need to be distinguished
+/// from other libraries of the same system, *e.g.*, 64-bits libraries on a multilib system
+/// that also runs 32-bits programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Architecture {
+    /// Default architecture of the system, *e.g.*, `i386` or 32-bits `powerpc`.
+    Default,
+    /// 64-bits SPARC (`FLAG_SPARC_LIB64`).
+    SparcLib64,
+    /// IA-64 (`FLAG_IA64_LIB64`).
+    IA64Lib64,
+    /// x86-64 (`FLAG_X8664_LIB64`).
+    X8664Lib64,
+    /// 64-bits s390 (`FLAG_S390_LIB64`).
+    S390Lib64,
+    /// 64-bits PowerPC (`FLAG_POWERPC_LIB64`).
+    PowerPCLib64,
+    /// MIPS64 with the n32 ABI (`FLAG_MIPS64_LIBN32`).
+    Mips64LibN32,
+    /// MIPS64 with the n64 ABI (`FLAG_MIPS64_LIBN64`).
+    Mips64LibN64,
+    /// x86-64 with the x32 ABI (`FLAG_X8664_LIBX32`).
+    X8664LibX32,
+    /// ARM with the hard-float ABI (`FLAG_ARM_LIBHF`).
+    ArmLibHF,
+    /// AArch64 (`FLAG_AARCH64_LIB64`).
+    AArch64Lib64,
+    /// ARM with the soft-float ABI (`FLAG_ARM_LIBSF`).
+    ArmLibSF,
+    /// 32-bits MIPS with the IEEE 754-2008 NaN encoding (`FLAG_MIPS_LIB32_NAN2008`).
+    MipsLib32Nan2008,
+    /// MIPS64 with the n32 ABI and the IEEE 754-2008 NaN encoding (`FLAG_MIPS64_LIBN32_NAN2008`).
+    Mips64LibN32Nan2008,
+    /// MIPS64 with the n64 ABI and the IEEE 754-2008 NaN encoding (`FLAG_MIPS64_LIBN64_NAN2008`).
+    Mips64LibN64Nan2008,
+    /// RISC-V with the soft-float ABI (`FLAG_RISCV_FLOAT_ABI_SOFT`).
+    RiscVFloatAbiSoft,
+    /// RISC-V with the double-precision floating-point ABI (`FLAG_RISCV_FLOAT_ABI_DOUBLE`).
+    RiscVFloatAbiDouble,
+    /// LoongArch with the soft-float ABI (`FLAG_LARCH_FLOAT_ABI_SOFT`).
+    LoongArchFloatAbiSoft,
+    /// LoongArch with the double-precision floating-point ABI (`FLAG_LARCH_FLOAT_ABI_DOUBLE`).
+    LoongArchFloatAbiDouble,
+    /// Unrecognized architecture.
+    Unknown(u8),
+}
+
+impl Architecture {
+    const fn from_raw(raw: u8) -> Self {
+        match raw {
+            0x00 => Self::Default,
+            0x01 => Self::SparcLib64,
+            0x02 => Self::IA64Lib64,
+            0x03 => Self::X8664Lib64,
+            0x04 => Self::S390Lib64,
+            0x05 => Self::PowerPCLib64,
+            0x06 => Self::Mips64LibN32,
+            0x07 => Self::Mips64LibN64,
+            0x08 => Self::X8664LibX32,
+            0x09 => Self::ArmLibHF,
+            0x0a => Self::AArch64Lib64,
+            0x0b => Self::ArmLibSF,
+            0x0c => Self::MipsLib32Nan2008,
+            0x0d => Self::Mips64LibN32Nan2008,
+            0x0e => Self::Mips64LibN64Nan2008,
+            0x0f => Self::RiscVFloatAbiSoft,
+            0x10 => Self::RiscVFloatAbiDouble,
+            0x11 => Self::LoongArchFloatAbiSoft,
+            0x12 => Self::LoongArchFloatAbiDouble,
+            _ => Self::Unknown(raw),
+        }
+    }
+
+    /// Return the ELF class of shared libraries built for this architecture,
+    /// if the architecture implies one.
+    #[must_use]
+    pub const fn elf_class(self) -> Option<ElfClass> {
+        match self {
+            Self::SparcLib64
+            | Self::IA64Lib64
+            | Self::X8664Lib64
+            | Self::S390Lib64
+            | Self::PowerPCLib64
+            | Self::Mips64LibN64
+            | Self::AArch64Lib64
+            | Self::Mips64LibN64Nan2008 => Some(ElfClass::Elf64),
+
+            Self::Mips64LibN32
+            | Self::X8664LibX32
+            | Self::ArmLibHF
+            | Self::ArmLibSF
+            | Self::MipsLib32Nan2008
+            | Self::Mips64LibN32Nan2008 => Some(ElfClass::Elf32),
+
+            Self::Default
+            | Self::RiscVFloatAbiSoft
+            | Self::RiscVFloatAbiDouble
+            | Self::LoongArchFloatAbiSoft
+            | Self::LoongArchFloatAbiDouble
+            | Self::Unknown(_) => None,
+        }
+    }
+}
+
+/// Section of the extension data of a cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExtensionSection<'cache> {
+    /// Description of the program that generated the cache, *e.g.*, the `ldconfig` version.
+    Generator(Cow<'cache, str>),
+    /// Names of the glibc-hwcaps subdirectories, *e.g.*, `x86-64-v3`.
+    ///
+    /// Entries located in a glibc-hwcaps subdirectory refer to it by its index in this list.
+    GLibCHWCaps(Vec<Cow<'cache, OsStr>>),
+    /// Section with an unrecognized tag.
+    Unknown {
+        /// Tag identifying the type of the section.
+        tag: u32,
+        /// Flags of the section.
+        flags: u32,
+        /// Raw data of the section.
+        bytes: &'cache [u8],
+    },
+}
+
+/// Minimum operating system version required by a shared library.
+///
+/// This is taken from the `NT_GNU_ABI_TAG` note of the shared library.
+/// The dynamic loader skips shared libraries that require a more recent kernel
+/// than the one currently running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OsVersion(u32);
+
+impl OsVersion {
+    /// Create an operating system version from its raw value, as stored in the cache.
+    #[must_use]
+    pub const fn from_raw(raw: u32) -> Self {
+        Self(raw)
+    }
+
+    /// Return the raw value of the operating system version, as stored in the cache.
+    #[must_use]
+    pub const fn raw(self) -> u32 {
+        self.0
+    }
+
+    /// Return the operating system identifier of the ABI tag (`0` for Linux).
+    #[must_use]
+    pub const fn os(self) -> u8 {
+        (self.0 >> 24) as u8
+    }
+
+    /// Return the minimum kernel version, as a `(major, minor, patch)` triple.
+    ///
+    /// Returns `None` if the shared library does not require a minimum kernel version.
+    #[must_use]
+    pub const fn kernel_version(self) -> Option<(u8, u8, u8)> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(((self.0 >> 16) as u8, (self.0 >> 8) as u8, self.0 as u8))
+        }
+    }
+}
+
+/// Hardware capabilities required by a shared library.
+///
+/// Starting from GNU C Library version 2.33, this either holds an index into the table
+/// of glibc-hwcaps subdirectories, or a set of legacy hardware capability bits.
+/// The upper 32 bits also hold the ISA level required by the shared library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HwCap(u64);
+
+impl HwCap {
+    /// Create hardware capabilities from their raw value, as stored in the cache.
+    #[must_use]
+    pub const fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
+    /// Return the raw value of the hardware capabilities, as stored in the cache.
+    #[must_use]
+    pub const fn raw(self) -> u64 {
+        self.0
+    }
+
+    /// Return `true` if the lower 32 bits are an index into
+    /// the table of glibc-hwcaps subdirectories.
+    #[must_use]
+    pub const fn is_extension(self) -> bool {
+        ((self.0 >> 32) as u32 & !HWCAP_ISA_LEVEL_MASK) == (HWCAP_EXTENSION >> 32) as u32
+    }
+
+    /// Return the index into the table of glibc-hwcaps subdirectories, if any.
+    #[must_use]
+    pub const fn extension_index(self) -> Option<u32> {
+        if self.is_extension() {
+            Some(self.0 as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Return the ISA level required by the shared library (`0` if none is required).
+    #[must_use]
+    pub const fn isa_level(self) -> u32 {
+        (self.0 >> 32) as u32 & HWCAP_ISA_LEVEL_MASK
+    }
+
+    /// Return the legacy hardware capability and platform bits.
+    ///
+    /// Returns `0` if the shared library is in a glibc-hwcaps subdirectory.
+    #[must_use]
+    pub const fn platform_bits(self) -> u64 {
+        if self.is_extension() {
+            0
+        } else {
+            self.0 & !((HWCAP_ISA_LEVEL_MASK as u64) << 32)
+        }
+    }
+}
+
 /// Cache of the GNU/Linux dynamic loader.
 ///
 /// This loads a dynamic loader cache file (*e.g.*, `/etc/ld.so.cache`),
 /// in the `glibc-ld.so.cache1.1` format, for either 32-bits or 64-bits architectures,
 /// in either little-endian or big-endian byte order.
+///
+/// The cache can also be embedded in a file in the old `ld.so-1.7.0` format,
+/// as written by `ldconfig` in its compatibility mode.
 #[derive(Debug)]
 pub struct Cache {
     path: PathBuf,
-    map: Mmap,
+    map: Arc<CacheBytes>,
+    /// Offset of the `glibc-ld.so.cache1.1` data in the file.
+    offset: usize,
     byte_order: Endianness,
     lib_count: u32,
+    extension_offset: u32,
 }
 
 impl Cache {
@@ -69,22 +435,91 @@ impl Cache {
     }
 
     /// Create a cache that loads the specified cache file.
+    ///
+    /// The file can either be in the `glibc-ld.so.cache1.1` format, or in the old `ld.so-1.7.0`
+    /// format with embedded `glibc-ld.so.cache1.1` data.
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::load_with_strategy(path, LoadStrategy::default())
+    }
+
+    /// Create a cache that loads the specified cache file, according to `strategy`.
+    ///
+    /// See [`Self::load()`].
+    pub fn load_with_strategy(path: impl AsRef<Path>, strategy: LoadStrategy) -> Result<Self> {
         let path = path.as_ref();
-        let map = map_file(path)?;
-        let (_, byte_order) =
-            Self::parse_byte_order(&map).map_err(|r| Error::from_nom_parse(r, &map, path))?;
-        let (_, lib_count) = Self::parse_header(&map, byte_order)
-            .map_err(|r| Error::from_nom_parse(r, &map, path))?;
+        load_file(
+            path,
+            strategy,
+            |map| Self::from_cache_bytes(path.into(), map),
+            Self::validate,
+        )
+    }
+
+    /// Create a cache that parses the specified bytes, instead of loading a file.
+    ///
+    /// The bytes can be owned by any type that can be viewed as `&[u8]`, *e.g.*, `Vec<u8>`,
+    /// `Arc<[u8]>` or `&'static [u8]`.
+    /// The path of the cache is empty. See [`Self::from_bytes_with_path()`].
+    pub fn from_bytes(bytes: impl AsRef<[u8]> + Send + Sync + 'static) -> Result<Self> {
+        Self::from_bytes_with_path(bytes, PathBuf::default())
+    }
+
+    /// Create a cache that parses the specified bytes, instead of loading a file.
+    ///
+    /// `path` is returned by [`Self::path()`] and reported in errors, but it is not accessed.
+    pub fn from_bytes_with_path(
+        bytes: impl AsRef<[u8]> + Send + Sync + 'static,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self> {
+        Self::from_cache_bytes(path.into(), CacheBytes::from_owner(bytes))
+    }
+
+    pub(crate) fn from_cache_bytes(path: PathBuf, map: CacheBytes) -> Result<Self> {
+        let byte_order = ld_so_1dot7::Cache::detect_byte_order(&map);
+        let offset = ld_so_1dot7::Cache::new_format_offset(&map, byte_order).unwrap_or(0);
+        Self::from_map(path, Arc::new(map), offset)
+    }
+
+    pub(crate) fn from_map(path: PathBuf, map: Arc<CacheBytes>, offset: usize) -> Result<Self> {
+        let bytes = map.get(offset..).unwrap_or_default();
+        if !bytes.starts_with(MAGIC) {
+            return Err(Error::MagicIsUnsupported { path });
+        }
+
+        let (_, byte_order) = Self::parse_byte_order(bytes)
+            .map_err(|r| Error::from_nom_parse_header(r, &map, &path))?;
+        let (_, (lib_count, extension_offset)) = Self::parse_header(bytes, byte_order)
+            .map_err(|r| Error::from_nom_parse_header(r, &map, &path))?;
 
         Ok(Self {
-            path: path.into(),
+            path,
             map,
+            offset,
             byte_order,
             lib_count,
+            extension_offset,
         })
     }
 
+    /// Return the `glibc-ld.so.cache1.1` data.
+    ///
+    /// Offsets of strings are relative to the start of this data,
+    /// while offsets of extension sections are relative to the start of the file.
+    fn bytes(&self) -> &[u8] {
+        &self.map[self.offset..]
+    }
+
+    /// Return the byte order of the specified bytes if they are a cache in this format.
+    pub(crate) fn detect(bytes: &[u8]) -> Option<ByteOrder> {
+        if !bytes.starts_with(MAGIC) {
+            return None;
+        }
+
+        // The flags of truncated caches might be missing, and loading them reports the issue.
+        let byte_order = Self::parse_byte_order(bytes).map_or(Endianness::Native, |(_, r)| r);
+        Some(ByteOrder::from_endianness(byte_order))
+    }
+
     fn parse_byte_order(bytes: &[u8]) -> IResult<&[u8], Endianness> {
         let (input, flags) = nom_preceded(nom_take(offset_of!(Header, flags)), nom_u8)(bytes)?;
 
@@ -100,12 +535,16 @@ impl Cache {
         }
     }
 
-    fn parse_header(bytes: &[u8], byte_order: Endianness) -> IResult<&[u8], u32> {
-        let (input, (lib_count, string_table_size)) = nom_tuple((
+    fn parse_header(bytes: &[u8], byte_order: Endianness) -> IResult<&[u8], (u32, u32)> {
+        let (input, (lib_count, string_table_size, extension_offset)) = nom_tuple((
             nom_preceded(nom_tag(MAGIC), nom_u32(byte_order)),
             nom_terminated(
                 nom_u32(byte_order),
-                nom_take(size_of::<Header>() - offset_of!(Header, flags)),
+                nom_take(offset_of!(Header, extension_offset) - offset_of!(Header, flags)),
+            ),
+            nom_terminated(
+                nom_u32(byte_order),
+                nom_take(size_of::<Header>() - offset_of!(Header, unused)),
             ),
         ))(bytes)?;
 
@@ -140,22 +579,139 @@ impl Cache {
 
         nom_peek(nom_take(min_size))(bytes)?;
 
-        Ok((input, lib_count))
+        Ok((input, (lib_count, extension_offset)))
+    }
+
+    fn parse_extension_header(
+        bytes: &[u8],
+        extension_offset: u32,
+        byte_order: Endianness,
+    ) -> IResult<&[u8], u32> {
+        let magic_bytes = match byte_order {
+            Endianness::Big => EXTENSION_MAGIC.to_be_bytes(),
+            Endianness::Little => EXTENSION_MAGIC.to_le_bytes(),
+            Endianness::Native => EXTENSION_MAGIC.to_ne_bytes(),
+        };
+
+        let (input, count) = nom_preceded(
+            nom_preceded(nom_take(extension_offset), nom_tag(magic_bytes)),
+            nom_u32(byte_order),
+        )(bytes)?;
+
+        let max_count = input
+            .len()
+            .saturating_div(size_of::<ExtensionSectionHeader>()) as u32;
+
+        if count > max_count {
+            return Err(nom::Err::Error(nom::error::make_error(
+                bytes,
+                nom::error::ErrorKind::TooLarge,
+            )));
+        }
+
+        Ok((input, count))
+    }
+
+    /// Return the path of the cache file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Decode all entries and extension sections of the cache, failing at the first invalid one.
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.iter_impl()?.try_for_each(|entry| entry.map(|_| ()))?;
+        self.extensions_impl()?
+            .try_for_each(|section| section.map(|_| ()))
     }
 
     /// Return an iterator that returns cache entries.
+    ///
+    /// This fails if the extension data of the cache is invalid, because the glibc-hwcaps
+    /// subdirectories of entries cannot be determined.
     pub fn iter(&self) -> Result<impl FusedIterator<Item = Result<crate::Entry<'_>>> + '_> {
+        self.iter_impl()
+    }
+
+    fn iter_impl(&self) -> Result<Iter<'_>> {
         let entries_end = size_of::<Header>()
             .saturating_add(size_of::<Entry>().saturating_mul(self.lib_count as usize));
-        let entries_bytes = &self.map[size_of::<Header>()..entries_end];
+        let entries_bytes = &self.bytes()[size_of::<Header>()..entries_end];
 
         Ok(Iter {
             path: &self.path,
             entries_bytes,
-            bytes: &self.map,
+            file_bytes: &self.map,
+            bytes: self.bytes(),
             byte_order: self.byte_order,
+            hwcaps: self.glibc_hwcaps()?,
         })
     }
+
+    /// Return an iterator that returns the cache entries whose file name is `name`.
+    ///
+    /// Similarly to the dynamic loader, this performs a binary search in the entries,
+    /// which `ldconfig` sorts according to [`lib_cmp()`].
+    /// Matching entries are adjacent, *e.g.*, one entry for each architecture or glibc-hwcaps
+    /// subdirectory, and are returned in their order in the cache.
+    pub fn find(
+        &self,
+        name: impl AsRef<OsStr>,
+    ) -> Result<impl FusedIterator<Item = Result<crate::Entry<'_>>> + '_> {
+        let name = name.as_ref().as_encoded_bytes();
+        let mut iter = self.iter_impl()?;
+
+        // Entries are sorted in descending order of file names.
+        let start = iter.partition_point(|key| lib_cmp(key, name).is_gt())?;
+        let end = iter.partition_point(|key| lib_cmp(key, name).is_ge())?;
+
+        let entries_bytes = iter.entries_bytes;
+        iter.entries_bytes = &entries_bytes[start.saturating_mul(size_of::<Entry>())..]
+            [..end.saturating_sub(start).saturating_mul(size_of::<Entry>())];
+        Ok(iter)
+    }
+
+    /// Return an iterator that returns the sections of the extension data of the cache.
+    ///
+    /// Caches generated by GNU C Library versions older than 2.33 have no extension data,
+    /// so the returned iterator is empty.
+    pub fn extensions(
+        &self,
+    ) -> Result<impl FusedIterator<Item = Result<ExtensionSection<'_>>> + '_> {
+        self.extensions_impl()
+    }
+
+    fn extensions_impl(&self) -> Result<ExtensionIter<'_>> {
+        let sections_bytes = if self.extension_offset == 0 {
+            &[][..]
+        } else {
+            let (input, count) =
+                Self::parse_extension_header(&self.map, self.extension_offset, self.byte_order)
+                    .map_err(|r| Error::from_nom_parse(r, &self.map, &self.path))?;
+
+            &input[..(count as usize).saturating_mul(size_of::<ExtensionSectionHeader>())]
+        };
+
+        Ok(ExtensionIter {
+            path: &self.path,
+            sections_bytes,
+            file_bytes: &self.map,
+            bytes: self.bytes(),
+            byte_order: self.byte_order,
+        })
+    }
+
+    /// Return the table of offsets of the names of glibc-hwcaps subdirectories.
+    fn glibc_hwcaps(&self) -> Result<&[u8]> {
+        let mut iter = self.extensions_impl()?;
+        while !iter.sections_bytes.is_empty() {
+            let (tag, _flags, bytes) = iter.next_raw()?;
+            if tag == EXTENSION_TAG_GLIBC_HWCAPS {
+                return Ok(bytes);
+            }
+        }
+        Ok(&[])
+    }
 }
 
 impl CacheProvider for Cache {
@@ -165,26 +721,99 @@ impl CacheProvider for Cache {
         let iter = self.iter()?;
         Ok(Box::new(iter))
     }
+
+    fn lookup<'cache>(
+        &'cache self,
+        name: &OsStr,
+    ) -> Result<Box<dyn FusedIterator<Item = Result<crate::Entry<'cache>>> + 'cache>> {
+        let iter = self.find(name)?;
+        Ok(Box::new(iter))
+    }
+}
+
+/// Compare the file names of shared libraries, similarly to `_dl_cache_libcmp()`
+/// of the GNU C Library.
+///
+/// Sequences of digits are compared as numbers, and digits are ordered after other characters.
+/// Other characters are compared as C `char` values, so the result depends on whether `char`
+/// is signed on the target architecture, as it does for the dynamic loader.
+/// Comparison stops at the first NUL character, if any.
+///
+/// `ldconfig` sorts the entries of a `glibc-ld.so.cache1.1` cache in descending order
+/// of their file names, according to this comparison.
+#[must_use]
+pub fn lib_cmp(left: &[u8], right: &[u8]) -> Ordering {
+    let signed = |c: u8| c_int::from(c_char::from_ne_bytes([c]));
+    let (mut left, mut right) = (until_nul(left), until_nul(right));
+
+    while let Some(&l) = left.first() {
+        let r = right.first().copied().unwrap_or_default();
+
+        match (l.is_ascii_digit(), r.is_ascii_digit()) {
+            (true, true) => {
+                let (l_value, l_rest) = parse_decimal(left);
+                let (r_value, r_rest) = parse_decimal(right);
+                if l_value != r_value {
+                    return l_value.wrapping_sub(r_value).cmp(&0_i32);
+                }
+                left = l_rest;
+                right = r_rest;
+            }
+
+            (true, false) => return Ordering::Greater,
+
+            (false, true) => return Ordering::Less,
+
+            (false, false) if l != r => return signed(l).cmp(&signed(r)),
+
+            (false, false) => {
+                left = &left[1..];
+                right = &right[1..];
+            }
+        }
+    }
+
+    let r = right.first().copied().unwrap_or_default();
+    0_i32.cmp(&signed(r))
+}
+
+/// Return the bytes that precede the first NUL character, if any.
+fn until_nul(bytes: &[u8]) -> &[u8] {
+    bytes.split(|&c| c == 0).next().unwrap_or_default()
+}
+
+/// Parse the leading decimal digits of `bytes`, and return their value and the remaining bytes.
+fn parse_decimal(bytes: &[u8]) -> (c_int, &[u8]) {
+    let count = bytes.iter().take_while(|c| c.is_ascii_digit()).count();
+    let (digits, rest) = bytes.split_at(count);
+    let value = digits.iter().fold(c_int::default(), |value, &c| {
+        value
+            .wrapping_mul(10_i32)
+            .wrapping_add(c_int::from(c - b'0'))
+    });
+    (value, rest)
 }
 
 #[derive(Debug)]
 struct Iter<'cache> {
     path: &'cache Path,
     entries_bytes: &'cache [u8],
+    file_bytes: &'cache [u8],
     bytes: &'cache [u8],
     byte_order: Endianness,
+    hwcaps: &'cache [u8],
 }
 
 impl<'cache> Iter<'cache> {
     fn next_fallible(&mut self) -> Result<crate::Entry<'cache>> {
-        let (input, (key, value)) = nom_tuple((
-            nom_preceded(nom_take(offset_of!(Entry, key)), nom_u32(self.byte_order)),
-            nom_terminated(
-                nom_u32(self.byte_order),
-                nom_take(size_of::<Entry>() - offset_of!(Entry, os_version)),
-            ),
+        let (input, (flags, key, value, os_version, hw_cap)) = nom_tuple((
+            nom_u32(self.byte_order),
+            nom_u32(self.byte_order),
+            nom_u32(self.byte_order),
+            nom_u32(self.byte_order),
+            nom_u64(self.byte_order),
         ))(self.entries_bytes)
-        .map_err(|r| Error::from_nom_parse(r, self.entries_bytes, self.path))?;
+        .map_err(|r| Error::from_nom_parse(r, self.file_bytes, self.path))?;
 
         self.entries_bytes = input;
 
@@ -204,7 +833,88 @@ impl<'cache> Iter<'cache> {
             })?;
         let value = CStr::from_bytes_until_nul(value)?;
 
-        cstr_entry_to_crate_entry(key, value)
+        let hw_cap = HwCap::from_raw(hw_cap);
+        let hwcaps_subdirectory = hw_cap
+            .extension_index()
+            .map(|index| self.hwcaps_subdirectory(index))
+            .transpose()?;
+
+        let metadata = crate::EntryMetadata::GLibCLdSOCache1dot1(EntryMetadata {
+            flags: Flags::from_raw(flags),
+            os_version: OsVersion::from_raw(os_version),
+            hw_cap,
+            hwcaps_subdirectory,
+        });
+
+        cstr_entry_to_crate_entry(
+            key,
+            value,
+            metadata,
+            CacheFormat::GLibCLdSOCache1dot1,
+            self.path,
+        )
+    }
+
+    /// Return the key of the entry at `index`.
+    fn key(&self, index: usize) -> Result<&'cache [u8]> {
+        let start = index
+            .saturating_mul(size_of::<Entry>())
+            .saturating_add(offset_of!(Entry, key));
+        let (_, key) = self
+            .entries_bytes
+            .get(start..)
+            .ok_or(Error::OffsetIsInvalid {
+                path: self.path.into(),
+            })
+            .and_then(|bytes| {
+                nom_u32(self.byte_order)(bytes)
+                    .map_err(|r| Error::from_nom_parse(r, self.file_bytes, self.path))
+            })?;
+
+        let key = self
+            .bytes
+            .get((key as usize)..)
+            .ok_or(Error::OffsetIsInvalid {
+                path: self.path.into(),
+            })?;
+        Ok(CStr::from_bytes_until_nul(key)?.to_bytes())
+    }
+
+    /// Return the index of the first entry whose key does not satisfy `predicate`,
+    /// assuming that the entries are partitioned according to `predicate`.
+    fn partition_point(&self, mut predicate: impl FnMut(&[u8]) -> bool) -> Result<usize> {
+        let (mut low, mut high) = (0, self.entries_bytes.len() / size_of::<Entry>());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if predicate(self.key(middle)?) {
+                low = middle.saturating_add(1);
+            } else {
+                high = middle;
+            }
+        }
+        Ok(low)
+    }
+
+    fn hwcaps_subdirectory(&self, index: u32) -> Result<Cow<'cache, OsStr>> {
+        let start = (index as usize).saturating_mul(size_of::<u32>());
+        let (_, offset) = self
+            .hwcaps
+            .get(start..)
+            .ok_or(Error::OffsetIsInvalid {
+                path: self.path.into(),
+            })
+            .and_then(|bytes| {
+                nom_u32(self.byte_order)(bytes)
+                    .map_err(|r| Error::from_nom_parse(r, self.file_bytes, self.path))
+            })?;
+
+        let name = self
+            .bytes
+            .get((offset as usize)..)
+            .ok_or(Error::OffsetIsInvalid {
+                path: self.path.into(),
+            })?;
+        cow_os_str_from_cstr(CStr::from_bytes_until_nul(name)?)
     }
 }
 
@@ -228,3 +938,85 @@ impl<'cache> Iterator for Iter<'cache> {
 impl<'cache> FusedIterator for Iter<'cache> {}
 
 impl<'cache> ExactSizeIterator for Iter<'cache> {}
+
+#[derive(Debug)]
+struct ExtensionIter<'cache> {
+    path: &'cache Path,
+    sections_bytes: &'cache [u8],
+    file_bytes: &'cache [u8],
+    bytes: &'cache [u8],
+    byte_order: Endianness,
+}
+
+impl<'cache> ExtensionIter<'cache> {
+    fn next_raw(&mut self) -> Result<(u32, u32, &'cache [u8])> {
+        let (input, (tag, flags, offset, size)) = nom_tuple((
+            nom_u32(self.byte_order),
+            nom_u32(self.byte_order),
+            nom_u32(self.byte_order),
+            nom_u32(self.byte_order),
+        ))(self.sections_bytes)
+        .map_err(|r| Error::from_nom_parse(r, self.file_bytes, self.path))?;
+
+        self.sections_bytes = input;
+
+        let bytes = self
+            .file_bytes
+            .get((offset as usize)..)
+            .and_then(|bytes| bytes.get(..(size as usize)))
+            .ok_or(Error::OffsetIsInvalid {
+                path: self.path.into(),
+            })?;
+
+        Ok((tag, flags, bytes))
+    }
+
+    fn next_fallible(&mut self) -> Result<ExtensionSection<'cache>> {
+        let (tag, flags, bytes) = self.next_raw()?;
+
+        match tag {
+            EXTENSION_TAG_GENERATOR => {
+                Ok(ExtensionSection::Generator(String::from_utf8_lossy(bytes)))
+            }
+
+            EXTENSION_TAG_GLIBC_HWCAPS => nom_many0(nom_u32(self.byte_order))(bytes)
+                .map_err(|r| Error::from_nom_parse(r, self.file_bytes, self.path))?
+                .1
+                .into_iter()
+                .map(|offset| {
+                    let name =
+                        self.bytes
+                            .get((offset as usize)..)
+                            .ok_or(Error::OffsetIsInvalid {
+                                path: self.path.into(),
+                            })?;
+                    cow_os_str_from_cstr(CStr::from_bytes_until_nul(name)?)
+                })
+                .collect::<Result<Vec<_>>>()
+                .map(ExtensionSection::GLibCHWCaps),
+
+            _ => Ok(ExtensionSection::Unknown { tag, flags, bytes }),
+        }
+    }
+}
+
+impl<'cache> Iterator for ExtensionIter<'cache> {
+    type Item = Result<ExtensionSection<'cache>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.sections_bytes.len() < size_of::<ExtensionSectionHeader>() {
+            None
+        } else {
+            Some(self.next_fallible())
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.sections_bytes.len() / size_of::<ExtensionSectionHeader>();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'cache> FusedIterator for ExtensionIter<'cache> {}
+
+impl<'cache> ExactSizeIterator for ExtensionIter<'cache> {}
@@ -1,11 +1,18 @@
+use core::cmp::Ordering;
 use core::mem::{offset_of, size_of};
+use std::ffi::OsStr;
 use std::io::{Cursor, Write};
+use std::path::Path;
 
 use assert_matches::assert_matches;
 use nom::number::Endianness;
 use proptest::prelude::*;
 
-use super::{Cache, Entry, Header, MAGIC};
+use super::{
+    lib_cmp, Architecture, Cache, Entry, ExtensionIter, ExtensionSection, Flags, Header, HwCap,
+    Iter, LibraryType, OsVersion, EXTENSION_MAGIC, MAGIC,
+};
+use crate::{ElfClass, EntryMetadata, Error, LoadStrategy};
 
 fn print_cache(cache: &Cache) {
     for e in cache.iter().unwrap() {
@@ -24,6 +31,306 @@ fn load() {
     print_cache(&cache);
 }
 
+#[test]
+fn entry_flags() {
+    let cache = Cache::load("tests/glibc-ld.so.cache1.1/ld.so.cache").unwrap();
+    let flags: Vec<_> = cache
+        .iter()
+        .unwrap()
+        .map(|e| match e.unwrap().metadata {
+            EntryMetadata::GLibCLdSOCache1dot1(metadata) => metadata.flags,
+            metadata => panic!("unexpected metadata: {metadata:?}"),
+        })
+        .take(3)
+        .collect();
+
+    assert_eq!(flags[0].library_type(), LibraryType::ElfLibc6);
+    assert_eq!(flags[0].architecture(), Architecture::X8664Lib64);
+    assert_eq!(flags[2].library_type(), LibraryType::ElfLibc6);
+    assert_eq!(flags[2].architecture(), Architecture::Default);
+}
+
+#[test]
+fn flags_from_raw() {
+    let flags = Flags::from_raw(0x0a03);
+    assert_eq!(flags.raw(), 0x0a03);
+    assert_eq!(flags.library_type(), LibraryType::ElfLibc6);
+    assert_eq!(flags.architecture(), Architecture::AArch64Lib64);
+    assert_eq!(flags.architecture().elf_class(), Some(ElfClass::Elf64));
+
+    let flags = Flags::from_raw(0x0803);
+    assert_eq!(flags.architecture(), Architecture::X8664LibX32);
+    assert_eq!(flags.architecture().elf_class(), Some(ElfClass::Elf32));
+
+    let flags = Flags::from_raw(0xff42);
+    assert_eq!(flags.library_type(), LibraryType::Unknown(0x42));
+    assert_eq!(flags.architecture(), Architecture::Unknown(0xff));
+    assert_eq!(flags.architecture().elf_class(), None);
+}
+
+#[test]
+fn hwcaps_subdirectories() {
+    let cache = Cache::load("tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps").unwrap();
+    let entries: Vec<_> = cache
+        .iter()
+        .unwrap()
+        .map(|e| {
+            let e = e.unwrap();
+            match e.metadata {
+                EntryMetadata::GLibCLdSOCache1dot1(metadata) => {
+                    (e.full_path, metadata.hwcaps_subdirectory)
+                }
+                metadata => panic!("unexpected metadata: {metadata:?}"),
+            }
+        })
+        .collect();
+
+    assert_eq!(entries.len(), 6);
+    assert_eq!(
+        entries[0].0,
+        Path::new("/usr/lib/glibc-hwcaps/x86-64-v3/libz.so.1")
+    );
+    assert_eq!(entries[0].1.as_deref(), Some(OsStr::new("x86-64-v3")));
+    assert_eq!(entries[1].1.as_deref(), Some(OsStr::new("x86-64-v2")));
+    assert_eq!(
+        entries[2].0,
+        Path::new("/usr/lib/x86_64-linux-gnu/libz.so.1")
+    );
+    assert_eq!(entries[2].1, None);
+}
+
+#[test]
+fn hwcaps_subdirectory_index_invalid() {
+    let mut bytes = std::fs::read("tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps").unwrap();
+    // Make the first entry refer to a glibc-hwcaps subdirectory that does not exist.
+    let hw_cap_offset = size_of::<Header>() + offset_of!(Entry, hw_cap);
+    bytes[hw_cap_offset..(hw_cap_offset + 8)]
+        .copy_from_slice(&0x4000_0000_0000_0002_u64.to_le_bytes());
+
+    let (_, (lib_count, extension_offset)) =
+        Cache::parse_header(&bytes, Endianness::Little).unwrap();
+    let hwcaps = {
+        let (input, count) =
+            Cache::parse_extension_header(&bytes, extension_offset, Endianness::Little).unwrap();
+        let mut iter = ExtensionIter {
+            path: Path::new("test"),
+            sections_bytes: &input[..(count as usize * 16)],
+            file_bytes: &bytes,
+            bytes: &bytes,
+            byte_order: Endianness::Little,
+        };
+        iter.next_raw().unwrap();
+        iter.next_raw().unwrap().2
+    };
+
+    let mut iter = Iter {
+        path: Path::new("test"),
+        entries_bytes: &bytes[size_of::<Header>()..][..(lib_count as usize * size_of::<Entry>())],
+        file_bytes: &bytes,
+        bytes: &bytes,
+        byte_order: Endianness::Little,
+        hwcaps,
+    };
+    assert_matches!(iter.next(), Some(Err(_)));
+    assert_matches!(iter.next(), Some(Ok(_)));
+}
+
+#[test]
+fn hwcaps_extension_invalid() {
+    // The glibc-hwcaps extension section of this cache is out of the bounds of the file.
+    let path = "tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps-bad-extension";
+    let cache = Cache::load(path).unwrap();
+
+    assert_matches!(cache.iter().err(), Some(Error::OffsetIsInvalid { .. }));
+    assert_matches!(
+        cache.find("libz.so.1").err(),
+        Some(Error::OffsetIsInvalid { .. })
+    );
+}
+
+#[test]
+fn os_version_from_raw() {
+    assert_eq!(OsVersion::from_raw(0).kernel_version(), None);
+
+    let os_version = OsVersion::from_raw(0x0003_020a);
+    assert_eq!(os_version.raw(), 0x0003_020a);
+    assert_eq!(os_version.os(), 0);
+    assert_eq!(os_version.kernel_version(), Some((3, 2, 10)));
+
+    assert_eq!(OsVersion::from_raw(0x0302_0000).os(), 3);
+}
+
+#[test]
+fn hw_cap_from_raw() {
+    let hw_cap = HwCap::from_raw(0);
+    assert!(!hw_cap.is_extension());
+    assert_eq!(hw_cap.extension_index(), None);
+    assert_eq!(hw_cap.isa_level(), 0);
+    assert_eq!(hw_cap.platform_bits(), 0);
+
+    let hw_cap = HwCap::from_raw(0x4000_0000_0000_0002);
+    assert!(hw_cap.is_extension());
+    assert_eq!(hw_cap.extension_index(), Some(2));
+    assert_eq!(hw_cap.isa_level(), 0);
+    assert_eq!(hw_cap.platform_bits(), 0);
+
+    let hw_cap = HwCap::from_raw(0x4000_0003_0000_0001);
+    assert!(hw_cap.is_extension());
+    assert_eq!(hw_cap.extension_index(), Some(1));
+    assert_eq!(hw_cap.isa_level(), 3);
+
+    let hw_cap = HwCap::from_raw(0x0001_0002_0000_0040);
+    assert!(!hw_cap.is_extension());
+    assert_eq!(hw_cap.isa_level(), 2);
+    assert_eq!(hw_cap.platform_bits(), 0x0001_0000_0000_0040);
+}
+
+#[test]
+fn extensions() {
+    let cache = Cache::load("tests/glibc-ld.so.cache1.1/ld.so.cache").unwrap();
+    let sections: Vec<_> = cache.extensions().unwrap().map(Result::unwrap).collect();
+
+    assert_matches!(
+        sections.as_slice(),
+        [ExtensionSection::Generator(generator)]
+            if generator.starts_with("ldconfig (Debian GLIBC 2.37-15)")
+    );
+}
+
+#[test]
+fn extension_sections() {
+    let mut bytes = Vec::new();
+    for n in [EXTENSION_MAGIC, 3] {
+        bytes.extend_from_slice(&n.to_le_bytes());
+    }
+    for n in [0_u32, 0, 56, 3, 1, 0, 60, 8, 7, 9, 56, 2] {
+        bytes.extend_from_slice(&n.to_le_bytes());
+    }
+    bytes.extend_from_slice(b"gen\0");
+    for n in [68_u32, 75] {
+        bytes.extend_from_slice(&n.to_le_bytes());
+    }
+    bytes.extend_from_slice(b"power9\0power10\0");
+
+    let (input, count) = Cache::parse_extension_header(&bytes, 0, Endianness::Little).unwrap();
+    assert_eq!(count, 3);
+
+    let sections: Vec<_> = ExtensionIter {
+        path: Path::new("test"),
+        sections_bytes: &input[..48],
+        file_bytes: &bytes,
+        bytes: &bytes,
+        byte_order: Endianness::Little,
+    }
+    .map(Result::unwrap)
+    .collect();
+
+    assert_eq!(
+        sections,
+        [
+            ExtensionSection::Generator("gen".into()),
+            ExtensionSection::GLibCHWCaps(vec![
+                OsStr::new("power9").into(),
+                OsStr::new("power10").into()
+            ]),
+            ExtensionSection::Unknown {
+                tag: 7,
+                flags: 9,
+                bytes: &b"ge"[..]
+            },
+        ]
+    );
+}
+
+#[test]
+fn extension_header_invalid() {
+    let mut bytes = Vec::new();
+    for n in [EXTENSION_MAGIC, 2, 0, 0, 0, 0_u32] {
+        bytes.extend_from_slice(&n.to_be_bytes());
+    }
+
+    Cache::parse_extension_header(&bytes, 0, Endianness::Big).unwrap_err();
+    Cache::parse_extension_header(&bytes, 0, Endianness::Little).unwrap_err();
+    Cache::parse_extension_header(&bytes, 4, Endianness::Big).unwrap_err();
+    Cache::parse_extension_header(&bytes, 1000, Endianness::Big).unwrap_err();
+
+    bytes.extend_from_slice(&[0; 16]);
+    Cache::parse_extension_header(&bytes, 0, Endianness::Big).unwrap();
+}
+
+#[test]
+fn load_compat() {
+    let cache = Cache::load("tests/ld.so-1.7.0/ld.so.cache.compat").unwrap();
+    assert_eq!(cache.iter().unwrap().count(), 2774);
+    assert_eq!(cache.iter().unwrap().filter(Result::is_err).count(), 0);
+    print_cache(&cache);
+}
+
+#[test]
+fn load_old_format() {
+    Cache::load("tests/ld.so-1.7.0/ld.so.cache").unwrap_err();
+}
+
+#[test]
+fn find() {
+    for path in [
+        "tests/glibc-ld.so.cache1.1/ld.so.cache",
+        "tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps",
+        "tests/ld.so-1.7.0/ld.so.cache.compat",
+    ] {
+        let cache = Cache::load(path).unwrap();
+
+        for entry in cache.iter().unwrap().map(Result::unwrap) {
+            let found: Vec<_> = cache
+                .find(&entry.file_name)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+
+            assert!(found.iter().all(|e| e.file_name == entry.file_name));
+            assert!(found.iter().any(|e| e.full_path == entry.full_path));
+        }
+
+        assert_eq!(cache.find("libmissing.so.1").unwrap().count(), 0);
+        assert_eq!(cache.find("").unwrap().count(), 0);
+    }
+}
+
+#[test]
+fn find_many() {
+    let cache = Cache::load("tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps").unwrap();
+    let full_paths: Vec<_> = cache
+        .find("libz.so.1")
+        .unwrap()
+        .map(|e| e.unwrap().full_path.into_owned())
+        .collect();
+    assert_eq!(full_paths.len(), 4);
+}
+
+#[test]
+fn lib_cmp_order() {
+    for (left, right, expected) in [
+        ("libc.so.6", "libc.so.6", Ordering::Equal),
+        ("libc.so.10", "libc.so.9", Ordering::Greater),
+        ("libc.so.09", "libc.so.9", Ordering::Equal),
+        ("libz.so.1", "libc.so.6", Ordering::Greater),
+        ("libc.so", "libc.so.6", Ordering::Less),
+        ("libc.so.6", "libc.so", Ordering::Greater),
+        ("lib1.so", "liba.so", Ordering::Greater),
+        ("liba.so", "lib1.so", Ordering::Less),
+        ("", "", Ordering::Equal),
+        ("", "a", Ordering::Less),
+        ("libc.so.6\0garbage", "libc.so.6", Ordering::Equal),
+        ("libc.so.6", "libc.so.6\0garbage", Ordering::Equal),
+    ] {
+        assert_eq!(
+            lib_cmp(left.as_bytes(), right.as_bytes()),
+            expected,
+            "{left} <=> {right}"
+        );
+    }
+}
+
 #[test]
 fn parse_byte_order_empty() {
     Cache::parse_byte_order(&[]).unwrap_err();
@@ -119,7 +426,7 @@ fn load_random0(
 
     assert_matches!(r, Ok((_, bo)) if bo == byte_order);
 
-    let Ok((_, lib_count)) = Cache::parse_header(&bytes, byte_order) else {
+    let Ok((_, (lib_count, _))) = Cache::parse_header(&bytes, byte_order) else {
         return Ok(());
     };
 
@@ -132,3 +439,55 @@ fn load_random0(
 
     Ok(())
 }
+
+proptest! {
+    #[test]
+    fn lib_cmp_antisymmetric(left in "lib[a-z0-9.+]{0,12}", right in "lib[a-z0-9.+]{0,12}") {
+        let order = lib_cmp(left.as_bytes(), right.as_bytes());
+        prop_assert_eq!(order, lib_cmp(right.as_bytes(), left.as_bytes()).reverse());
+        prop_assert_eq!(lib_cmp(left.as_bytes(), left.as_bytes()), Ordering::Equal);
+    }
+}
+
+#[test]
+fn from_bytes() {
+    let path = "tests/glibc-ld.so.cache1.1/ld.so.cache";
+    let entries = |cache: &Cache| -> Vec<(std::ffi::OsString, std::path::PathBuf)> {
+        cache
+            .iter()
+            .unwrap()
+            .map(|e| e.unwrap())
+            .map(|e| (e.file_name.into_owned(), e.full_path.into_owned()))
+            .collect()
+    };
+
+    let expected = entries(&Cache::load(path).unwrap());
+
+    let cache = Cache::from_bytes(std::fs::read(path).unwrap()).unwrap();
+    assert_eq!(cache.path(), Path::new(""));
+    assert_eq!(entries(&cache), expected);
+
+    let bytes: &'static [u8] = include_bytes!("../../tests/glibc-ld.so.cache1.1/ld.so.cache");
+    let cache = Cache::from_bytes_with_path(bytes, "memory").unwrap();
+    assert_eq!(cache.path(), Path::new("memory"));
+    assert_eq!(entries(&cache), expected);
+
+    assert_matches!(
+        Cache::from_bytes_with_path(&b"glibc-ld.so.cache1.0"[..], "memory"),
+        Err(Error::MagicIsUnsupported { path }) if path == Path::new("memory")
+    );
+}
+
+#[test]
+fn load_map_verified_invalid() {
+    // Entries are decoded when the cache is loaded and verified, instead of when it is iterated.
+    let path = "tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps-bad-extension";
+    Cache::load_with_strategy(path, LoadStrategy::Map).unwrap();
+    assert_matches!(
+        Cache::load_with_strategy(path, LoadStrategy::MapVerified),
+        Err(Error::OffsetIsInvalid { .. })
+    );
+
+    let path = "tests/glibc-ld.so.cache1.1/ld.so.cache";
+    Cache::load_with_strategy(path, LoadStrategy::MapVerified).unwrap();
+}
@@ -12,11 +12,11 @@ mod tests;
 use core::iter::FusedIterator;
 use core::mem::{offset_of, size_of};
 use std::borrow::Cow;
+use std::ffi::OsStr;
 use std::fs::read_dir;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-use memmap2::Mmap;
 use nom::bytes::complete::{tag as nom_tag, take as nom_take};
 use nom::combinator::peek as nom_peek;
 use nom::number::complete::u32 as nom_u32;
@@ -24,11 +24,11 @@ use nom::number::Endianness;
 use nom::sequence::{preceded as nom_preceded, terminated as nom_terminated, tuple as nom_tuple};
 use nom::IResult;
 
-use crate::utils::{map_file, path_from_bytes};
-use crate::{CacheProvider, Error, Result};
+use crate::utils::{elf_class_of_file, load_file, path_from_bytes, resolve_in_root, CacheBytes};
+use crate::{ByteOrder, CacheFormat, CacheProvider, ElfClass, Error, LoadStrategy, Result};
 
-pub(crate) static CACHE_FILE_PATHS: &[&str] =
-    &["/var/run/ld-elf.so.hints", "/var/run/ld-elf32.so.hints"];
+pub(crate) static CACHE_FILE_PATH: &str = "/var/run/ld-elf.so.hints";
+pub(crate) static CACHE_FILE_PATH_32: &str = "/var/run/ld-elf32.so.hints";
 
 const MAGIC: u32 = 0x74_6e_68_45;
 const MAGIC_LE32: [u8; 4] = MAGIC.to_le_bytes();
@@ -54,6 +54,18 @@ struct Header {
     spare: [u32; 26],
 }
 
+/// Information about a cache entry, specific to the `ld-elf.so.hints` format.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct EntryMetadata {
+    /// ABI of the cache that returned the entry.
+    ///
+    /// This is [`ElfClass::Elf32`] for entries returned by the cache of 32-bits compatibility
+    /// libraries (*e.g.*, `/var/run/ld-elf32.so.hints`).
+    pub abi: ElfClass,
+}
+
 /// Cache of the FreeBSD dynamic loader.
 ///
 /// This loads a dynamic loader cache file
@@ -61,29 +73,115 @@ struct Header {
 /// for either 32-bits or 64-bits architectures, in either little-endian or big-endian byte order.
 #[derive(Debug)]
 pub struct Cache {
-    map: Mmap,
+    path: PathBuf,
+    map: CacheBytes,
+    abi: ElfClass,
+    root: Option<PathBuf>,
     dir_list_offset: u32,
     dir_list_size: u32,
 }
 
 impl Cache {
+    /// Create a cache that loads the file `/var/run/ld-elf.so.hints`.
+    pub fn load_default() -> Result<Self> {
+        Self::load_for_abi(ElfClass::NATIVE)
+    }
+
+    /// Create a cache that loads the cache file of the dynamic loader of the specified ABI.
+    ///
+    /// This loads `/var/run/ld-elf.so.hints` for the native ABI, and `/var/run/ld-elf32.so.hints`
+    /// for the 32-bits compatibility ABI of 64-bits systems.
+    pub fn load_for_abi(abi: ElfClass) -> Result<Self> {
+        let path = match (abi, ElfClass::NATIVE) {
+            (ElfClass::Elf32, ElfClass::Elf32) | (ElfClass::Elf64, ElfClass::Elf64) => {
+                CACHE_FILE_PATH
+            }
+            (ElfClass::Elf32, ElfClass::Elf64) => CACHE_FILE_PATH_32,
+            (ElfClass::Elf64, ElfClass::Elf32) => return Err(Error::AbiIsUnsupported { abi }),
+        };
+        Self::load_with_abi(path, abi)
+    }
+
     /// Create a cache that loads the specified cache file.
+    ///
+    /// The ABI of the cache is [`ElfClass::Elf32`] if the file is named `ld-elf32.so.hints`,
+    /// and [`ElfClass::NATIVE`] otherwise.
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::load_with_strategy(path, LoadStrategy::default())
+    }
+
+    /// Create a cache that loads the specified cache file, according to `strategy`.
+    ///
+    /// See [`Self::load()`].
+    pub fn load_with_strategy(path: impl AsRef<Path>, strategy: LoadStrategy) -> Result<Self> {
         let path = path.as_ref();
-        let map = map_file(path)?;
-        let (_, byte_order) =
-            Self::parse_byte_order(&map).map_err(|r| Error::from_nom_parse(r, &map, path))?;
+        Self::load_impl(path, abi_of_path(path), strategy)
+    }
+
+    /// Create a cache that loads the specified cache file, listing libraries of the specified ABI.
+    pub fn load_with_abi(path: impl AsRef<Path>, abi: ElfClass) -> Result<Self> {
+        Self::load_impl(path.as_ref(), abi, LoadStrategy::default())
+    }
+
+    pub(crate) fn load_impl(path: &Path, abi: ElfClass, strategy: LoadStrategy) -> Result<Self> {
+        load_file(
+            path,
+            strategy,
+            |map| Self::from_map(path.into(), map, abi),
+            Self::validate,
+        )
+    }
+
+    /// Create a cache that parses the specified bytes, instead of loading a file.
+    ///
+    /// The bytes can be owned by any type that can be viewed as `&[u8]`, *e.g.*, `Vec<u8>`,
+    /// `Arc<[u8]>` or `&'static [u8]`.
+    /// The path of the cache is empty, and its ABI is [`ElfClass::NATIVE`].
+    /// See [`Self::from_bytes_with_path()`].
+    pub fn from_bytes(bytes: impl AsRef<[u8]> + Send + Sync + 'static) -> Result<Self> {
+        Self::from_bytes_with_path(bytes, PathBuf::default())
+    }
+
+    /// Create a cache that parses the specified bytes, instead of loading a file.
+    ///
+    /// `path` is returned by [`Self::path()`] and reported in errors, but it is not accessed.
+    /// The ABI of the cache is inferred from `path`, similarly to [`Self::load()`].
+    pub fn from_bytes_with_path(
+        bytes: impl AsRef<[u8]> + Send + Sync + 'static,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self> {
+        Self::from_cache_bytes(path.into(), CacheBytes::from_owner(bytes))
+    }
+
+    pub(crate) fn from_cache_bytes(path: PathBuf, map: CacheBytes) -> Result<Self> {
+        let abi = abi_of_path(&path);
+        Self::from_map(path, map, abi)
+    }
+
+    fn from_map(path: PathBuf, map: CacheBytes, abi: ElfClass) -> Result<Self> {
+        let Ok((_, byte_order)) = Self::parse_byte_order(&map) else {
+            return Err(Error::MagicIsUnsupported { path });
+        };
         let (_, (string_table_offset, dir_list_offset, dir_list_size)) =
             Self::parse_header(&map, byte_order)
-                .map_err(|r| Error::from_nom_parse(r, &map, path))?;
+                .map_err(|r| Error::from_nom_parse_header(r, &map, &path))?;
 
         Ok(Self {
+            path,
             map,
+            abi,
+            root: None,
             dir_list_offset: string_table_offset.saturating_add(dir_list_offset),
             dir_list_size,
         })
     }
 
+    /// Return the byte order of the specified bytes if they are a cache in this format.
+    pub(crate) fn detect(bytes: &[u8]) -> Option<ByteOrder> {
+        let (_, byte_order) = Self::parse_byte_order(bytes).ok()?;
+        Some(ByteOrder::from_endianness(byte_order))
+    }
+
     fn parse_byte_order(bytes: &[u8]) -> IResult<&[u8], Endianness> {
         nom_tag::<&[u8], &[u8], nom::error::Error<&[u8]>>(&MAGIC_LE32)(bytes)
             .map(|(input, _)| (input, Endianness::Little))
@@ -156,38 +254,182 @@ impl Cache {
         Ok((input, (string_table_offset, dir_list_offset, dir_list_size)))
     }
 
-    /// Return an iterator that returns cache entries.
-    pub fn iter(&self) -> Result<impl FusedIterator<Item = Result<crate::Entry<'_>>> + '_> {
+    /// Return the path of the cache file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Decode all directories of the cache, failing at the first invalid one.
+    ///
+    /// Entries are not decoded, because they are read from the directories, not from the cache.
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.directories()?
+            .try_for_each(|directory| directory.map(|_| ()))
+    }
+
+    /// Return this cache, after setting the root directory of the system it belongs to.
+    ///
+    /// The directories of the cache are then scanned under `root`, instead of the root directory
+    /// of the host, similarly to `chroot`.
+    /// The paths of entries remain relative to the system the cache belongs to.
+    #[must_use]
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Return the root directory of the system this cache belongs to, if it was set.
+    #[must_use]
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Return the path on the host of the specified path of the system this cache belongs to.
+    pub(crate) fn host_path<'path>(&self, path: &'path Path) -> Cow<'path, Path> {
+        match &self.root {
+            Some(root) => Cow::Owned(resolve_in_root(root, path)),
+            None => Cow::Borrowed(path),
+        }
+    }
+
+    /// Return the ABI of the libraries listed by this cache.
+    #[must_use]
+    pub fn abi(&self) -> ElfClass {
+        self.abi
+    }
+
+    /// Return an iterator that returns the directories searched by the dynamic loader.
+    ///
+    /// These are the directories listed in the header of the cache, in their original order.
+    pub fn directories(&self) -> Result<impl FusedIterator<Item = Result<Cow<'_, Path>>> + '_> {
         let start = self.dir_list_offset as usize;
         let bytes = &self.map[start..start.saturating_add(self.dir_list_size as usize)];
 
         let iter = bytes
             .split(|&b| b == b':')
-            .map(path_from_bytes)
+            .filter(|bytes| !bytes.is_empty())
+            .map(path_from_bytes);
+
+        Ok(iter)
+    }
+
+    /// Return an iterator that returns cache entries.
+    ///
+    /// Only the shared libraries of the ABI of the cache are returned,
+    /// as with [`ScanMode::SharedObjects`].
+    pub fn iter(&self) -> Result<impl FusedIterator<Item = Result<crate::Entry<'_>>> + '_> {
+        self.iter_with_scan_mode(ScanMode::SharedObjects)
+    }
+
+    /// Return an iterator that returns cache entries, selected as specified by `scan_mode`.
+    pub fn iter_with_scan_mode(
+        &self,
+        scan_mode: ScanMode,
+    ) -> Result<impl FusedIterator<Item = Result<crate::Entry<'_>>> + '_> {
+        let abi = self.abi;
+        let metadata = EntryMetadata { abi };
+
+        let iter = self
+            .directories()?
             .filter_map(Result::ok)
             .map(Rc::new)
             .filter_map(|path| {
-                read_dir(path.as_ref().as_ref())
+                read_dir(self.host_path(&path))
                     .ok()
                     .map(move |dirs| dirs.map(move |entries| (Rc::clone(&path), entries)))
             })
             .flatten()
-            .map(|(path, entry)| match entry {
+            .map(move |(path, entry)| match entry {
                 Ok(entry) => Ok(crate::Entry {
+                    full_path: Cow::Owned(path.join(entry.file_name())),
                     file_name: Cow::Owned(entry.file_name()),
-                    full_path: Cow::Owned(entry.path()),
+                    metadata: crate::EntryMetadata::LdELFSOHints(metadata.clone()),
+                    cache_format: CacheFormat::LdELFSOHints,
+                    cache_path: Cow::Borrowed(&self.path),
                 }),
 
                 Err(source) => {
                     let path = path.as_ref().as_ref().into();
                     Err(Error::ReadDir { path, source })
                 }
+            })
+            .filter(move |entry| match (scan_mode, entry) {
+                (ScanMode::SharedObjects, Ok(entry)) => {
+                    is_shared_object_name(&entry.file_name)
+                        && elf_class_of_file(&self.host_path(&entry.full_path)) == Some(abi)
+                }
+                (ScanMode::All, _) | (_, Err(_)) => true,
+            });
+
+        Ok(iter)
+    }
+
+    /// Return an iterator that returns the cache entries whose file name is `name`.
+    ///
+    /// Instead of scanning the directories of the cache, this probes each directory for a file
+    /// named `name`, which is selected as with [`ScanMode::SharedObjects`].
+    pub fn lookup(
+        &self,
+        name: impl AsRef<OsStr>,
+    ) -> Result<impl FusedIterator<Item = Result<crate::Entry<'_>>> + '_> {
+        let name = name.as_ref();
+        let abi = self.abi;
+        let metadata = EntryMetadata { abi };
+
+        // Names that include directories are not searched in directories.
+        let is_valid = Path::new(name).file_name() == Some(name) && is_shared_object_name(name);
+        let file_name = name.to_os_string();
+
+        let iter = self
+            .directories()?
+            .filter(move |_| is_valid)
+            .filter_map(Result::ok)
+            .map(move |directory| directory.join(&file_name))
+            .filter(move |full_path| elf_class_of_file(&self.host_path(full_path)) == Some(abi))
+            .map(move |full_path| {
+                Ok(crate::Entry {
+                    file_name: Cow::Owned(full_path.file_name().unwrap_or_default().into()),
+                    full_path: Cow::Owned(full_path),
+                    metadata: crate::EntryMetadata::LdELFSOHints(metadata.clone()),
+                    cache_format: CacheFormat::LdELFSOHints,
+                    cache_path: Cow::Borrowed(&self.path),
+                })
             });
 
         Ok(iter)
     }
 }
 
+/// Selection of the directory entries returned by [`Cache::iter_with_scan_mode()`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ScanMode {
+    /// Select files named `lib*.so*` that are ELF files of the ABI of the cache.
+    ///
+    /// Symbolic links are followed, and dangling ones are skipped.
+    #[default]
+    SharedObjects,
+    /// Select all entries of the directories, including subdirectories and files of any kind.
+    All,
+}
+
+/// Return the ABI of the cache file at `path`, inferred from its file name.
+pub(crate) fn abi_of_path(path: &Path) -> ElfClass {
+    if path.file_name() == Path::new(CACHE_FILE_PATH_32).file_name() {
+        ElfClass::Elf32
+    } else {
+        ElfClass::NATIVE
+    }
+}
+
+/// Return `true` if `file_name` matches `lib*.so*`.
+fn is_shared_object_name(file_name: &OsStr) -> bool {
+    file_name
+        .as_encoded_bytes()
+        .strip_prefix(b"lib")
+        .is_some_and(|rest| rest.windows(3).any(|w| w == b".so"))
+}
+
 impl CacheProvider for Cache {
     fn entries_iter<'cache>(
         &'cache self,
@@ -195,4 +437,12 @@ impl CacheProvider for Cache {
         let iter = self.iter()?;
         Ok(Box::new(iter))
     }
+
+    fn lookup<'cache>(
+        &'cache self,
+        name: &OsStr,
+    ) -> Result<Box<dyn FusedIterator<Item = Result<crate::Entry<'cache>>> + 'cache>> {
+        let iter = self.lookup(name)?;
+        Ok(Box::new(iter))
+    }
 }
@@ -1,4 +1,8 @@
-use super::Cache;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use super::{is_shared_object_name, Cache, EntryMetadata, ScanMode};
+use crate::ElfClass;
 
 fn print_cache(cache: &Cache) {
     for e in cache.iter().unwrap() {
@@ -23,7 +27,183 @@ fn load_32bits() {
     print_cache(&cache);
 }
 
+#[test]
+fn directories() {
+    let cache = Cache::load("tests/ld-elf.so.hints/ld-elf32.so.hints").unwrap();
+    let directories: Vec<_> = cache.directories().unwrap().map(Result::unwrap).collect();
+    assert_eq!(directories, [Path::new("/usr/lib32")]);
+
+    let cache = Cache::load("tests/ld-elf.so.hints/ld-elf.so.hints").unwrap();
+    let directories: Vec<_> = cache.directories().unwrap().map(Result::unwrap).collect();
+    assert_eq!(directories.len(), 15);
+    assert_eq!(directories[0], Path::new("/lib"));
+}
+
+#[test]
+fn abi() {
+    let cache = Cache::load("tests/ld-elf.so.hints/ld-elf.so.hints").unwrap();
+    assert_eq!(cache.abi(), ElfClass::NATIVE);
+
+    let cache = Cache::load("tests/ld-elf.so.hints/ld-elf32.so.hints").unwrap();
+    assert_eq!(cache.abi(), ElfClass::Elf32);
+
+    let cache =
+        Cache::load_with_abi("tests/ld-elf.so.hints/ld-elf.so.hints", ElfClass::Elf32).unwrap();
+    assert_eq!(cache.abi(), ElfClass::Elf32);
+
+    for e in cache.iter().unwrap() {
+        let expected = EntryMetadata {
+            abi: ElfClass::Elf32,
+        };
+        assert_eq!(
+            e.unwrap().metadata,
+            crate::EntryMetadata::LdELFSOHints(expected)
+        );
+    }
+}
+
+fn scanned_file_names(cache: &Cache, scan_mode: ScanMode) -> Vec<String> {
+    let mut names: Vec<_> = cache
+        .iter_with_scan_mode(scan_mode)
+        .unwrap()
+        .map(|e| e.unwrap().file_name.into_owned().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn scan_shared_objects() {
+    let path = "tests/ld-elf.so.hints/ld-elf.so.hints-scan";
+
+    let cache = Cache::load_with_abi(path, ElfClass::Elf64).unwrap();
+    assert_eq!(
+        scanned_file_names(&cache, ScanMode::SharedObjects),
+        ["libc.so", "libc.so.7", "libm.so.5"]
+    );
+    assert_eq!(cache.iter().unwrap().count(), 3);
+
+    let cache = Cache::load_with_abi(path, ElfClass::Elf32).unwrap();
+    assert_eq!(
+        scanned_file_names(&cache, ScanMode::SharedObjects),
+        ["libc32.so.7"]
+    );
+}
+
+#[test]
+fn scan_all() {
+    let cache = Cache::load("tests/ld-elf.so.hints/ld-elf.so.hints-scan").unwrap();
+    assert_eq!(
+        scanned_file_names(&cache, ScanMode::All),
+        [
+            "README",
+            "crt1.o",
+            "libc.a",
+            "libc.so",
+            "libc.so.7",
+            "libc32.so.7",
+            "libdir.so.1",
+            "libgone.so.1",
+            "libm.so.5",
+            "libtext.so.1",
+        ]
+    );
+}
+
+#[test]
+fn lookup() {
+    let path = "tests/ld-elf.so.hints/ld-elf.so.hints-scan";
+    let cache = Cache::load_with_abi(path, ElfClass::Elf64).unwrap();
+    let lookup = |cache: &Cache, name: &str| -> Vec<PathBuf> {
+        cache
+            .lookup(name)
+            .unwrap()
+            .map(|e| e.unwrap().full_path.into_owned())
+            .collect()
+    };
+
+    assert_eq!(
+        lookup(&cache, "libc.so.7"),
+        [Path::new("tests/ld-elf.so.hints/lib/libc.so.7")]
+    );
+    assert_eq!(
+        lookup(&cache, "libc.so"),
+        [Path::new("tests/ld-elf.so.hints/lib/libc.so")]
+    );
+    for name in [
+        "libc32.so.7",
+        "libgone.so.1",
+        "libdir.so.1",
+        "libtext.so.1",
+        "libc.a",
+        "crt1.o",
+        "../lib/libc.so.7",
+        "",
+    ] {
+        assert!(lookup(&cache, name).is_empty(), "{name}");
+    }
+
+    let cache = Cache::load_with_abi(path, ElfClass::Elf32).unwrap();
+    assert_eq!(
+        lookup(&cache, "libc32.so.7"),
+        [Path::new("tests/ld-elf.so.hints/lib/libc32.so.7")]
+    );
+    assert!(lookup(&cache, "libc.so.7").is_empty());
+}
+
+#[test]
+fn shared_object_names() {
+    for name in ["libc.so", "libc.so.7", "libc.so.7.debug", "lib.so"] {
+        assert!(is_shared_object_name(OsStr::new(name)), "{name}");
+    }
+    for name in ["libc.a", "c.so.7", "README", "libso", "crt1.o"] {
+        assert!(!is_shared_object_name(OsStr::new(name)), "{name}");
+    }
+}
+
 #[test]
 fn parse_byte_order_empty() {
     Cache::parse_byte_order(&[]).unwrap_err();
 }
+
+#[test]
+fn root() {
+    let path = "tests/root/var/run/ld-elf.so.hints";
+    let cache = Cache::load_with_abi(path, ElfClass::Elf64).unwrap();
+    assert_eq!(cache.root(), None);
+    assert_eq!(cache.iter().unwrap().count(), 0);
+
+    // The directory `/lib` is a symbolic link to `/fixture/lib`, which only exists in the root directory.
+    let cache = cache.with_root("tests/root");
+    assert_eq!(cache.root(), Some(Path::new("tests/root")));
+
+    let paths: Vec<_> = cache
+        .iter()
+        .unwrap()
+        .map(|e| e.unwrap().full_path.into_owned())
+        .collect();
+    assert_eq!(paths, [Path::new("/lib/libc.so.7")]);
+
+    let paths: Vec<_> = cache
+        .lookup("libc.so.7")
+        .unwrap()
+        .map(|e| e.unwrap().full_path.into_owned())
+        .collect();
+    assert_eq!(paths, [Path::new("/lib/libc.so.7")]);
+}
+
+#[test]
+fn from_bytes() {
+    let bytes = std::fs::read("tests/ld-elf.so.hints/ld-elf.so.hints-scan").unwrap();
+
+    let cache = Cache::from_bytes(bytes.clone()).unwrap();
+    assert_eq!(cache.abi(), ElfClass::NATIVE);
+    assert_eq!(
+        cache.directories().unwrap().next().unwrap().unwrap(),
+        Path::new("tests/ld-elf.so.hints/lib")
+    );
+
+    let cache = Cache::from_bytes_with_path(bytes, "/var/run/ld-elf32.so.hints").unwrap();
+    assert_eq!(cache.abi(), ElfClass::Elf32);
+    assert_eq!(cache.path(), Path::new("/var/run/ld-elf32.so.hints"));
+}
@@ -11,10 +11,10 @@ mod tests;
 
 use core::ffi::{c_uint, CStr};
 use core::iter::FusedIterator;
-use core::mem::{offset_of, size_of};
+use core::mem::{align_of, offset_of, size_of};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use memmap2::Mmap;
 use nom::bytes::complete::{tag as nom_tag, take as nom_take};
 use nom::combinator::peek as nom_peek;
 use nom::number::complete::u32 as nom_u32;
@@ -23,10 +23,13 @@ use nom::sequence::{preceded as nom_preceded, tuple as nom_tuple};
 use nom::IResult;
 use static_assertions::assert_eq_size;
 
-use crate::utils::{cstr_entry_to_crate_entry, map_file};
-use crate::{CacheProvider, Error, Result};
+use crate::utils::{cstr_entry_to_crate_entry, load_file, CacheBytes};
+use crate::{
+    glibc_ld_so_cache_1dot1, ByteOrder, CacheFormat, CacheProvider, EntryMetadata, Error,
+    LoadStrategy, Result,
+};
 
-static CACHE_FILE_PATH: &str = "/etc/ld.so.cache";
+pub(crate) static CACHE_FILE_PATH: &str = "/etc/ld.so.cache";
 
 static MAGIC: &[u8] = b"ld.so-1.7.0";
 
@@ -49,10 +52,18 @@ struct Entry {
 /// This loads a dynamic loader cache file (*e.g.*, `/etc/ld.so.cache`),
 /// in the old `ld.so-1.7.0` format, for either 32-bits or 64-bits architectures,
 /// in either little-endian or big-endian byte order.
+///
+/// The layout of this format does not depend on the architecture, so files generated
+/// for 32-bits or 64-bits architectures are read in the same way. The byte order is either
+/// detected automatically, or specified explicitly by [`Cache::load_with_byte_order()`].
+///
+/// When `ldconfig` runs in its compatibility mode, it embeds a cache in the newer
+/// `glibc-ld.so.cache1.1` format after the old entries. See [`Cache::new_format()`].
 #[derive(Debug)]
 pub struct Cache {
     path: PathBuf,
-    map: Mmap,
+    map: Arc<CacheBytes>,
+    byte_order: Endianness,
     lib_count: u32,
 }
 
@@ -63,20 +74,149 @@ impl Cache {
     }
 
     /// Create a cache that loads the specified cache file.
+    ///
+    /// The byte order of the file is detected automatically, preferring the native byte order
+    /// when the file is plausible in both byte orders.
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::load_with_strategy(path, LoadStrategy::default())
+    }
+
+    /// Create a cache that loads the specified cache file, according to `strategy`.
+    ///
+    /// See [`Self::load()`].
+    pub fn load_with_strategy(path: impl AsRef<Path>, strategy: LoadStrategy) -> Result<Self> {
         let path = path.as_ref();
-        let map = map_file(path)?;
-        let (_, lib_count) =
-            Self::parse_header(&map).map_err(|r| Error::from_nom_parse(r, &map, path))?;
+        load_file(
+            path,
+            strategy,
+            |map| Self::from_cache_bytes(path.into(), map),
+            Self::validate,
+        )
+    }
+
+    /// Create a cache that loads the specified cache file, in the specified byte order,
+    /// according to `strategy`.
+    ///
+    /// The cache embedded in the `glibc-ld.so.cache1.1` format, if any, is located assuming
+    /// the same byte order. See [`Self::new_format()`].
+    pub fn load_with_byte_order(
+        path: impl AsRef<Path>,
+        byte_order: ByteOrder,
+        strategy: LoadStrategy,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        load_file(
+            path,
+            strategy,
+            |map| Self::from_map(path.into(), map, byte_order.endianness()),
+            Self::validate,
+        )
+    }
+
+    /// Create a cache that parses the specified bytes, instead of loading a file.
+    ///
+    /// The bytes can be owned by any type that can be viewed as `&[u8]`, *e.g.*, `Vec<u8>`,
+    /// `Arc<[u8]>` or `&'static [u8]`.
+    /// The path of the cache is empty. See [`Self::from_bytes_with_path()`].
+    pub fn from_bytes(bytes: impl AsRef<[u8]> + Send + Sync + 'static) -> Result<Self> {
+        Self::from_bytes_with_path(bytes, PathBuf::default())
+    }
+
+    /// Create a cache that parses the specified bytes, instead of loading a file.
+    ///
+    /// `path` is returned by [`Self::path()`] and reported in errors, but it is not accessed.
+    pub fn from_bytes_with_path(
+        bytes: impl AsRef<[u8]> + Send + Sync + 'static,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self> {
+        Self::from_cache_bytes(path.into(), CacheBytes::from_owner(bytes))
+    }
+
+    pub(crate) fn from_cache_bytes(path: PathBuf, map: CacheBytes) -> Result<Self> {
+        let byte_order = Self::detect_byte_order(&map);
+        Self::from_map(path, map, byte_order)
+    }
+
+    fn from_map(path: PathBuf, map: CacheBytes, byte_order: Endianness) -> Result<Self> {
+        if !map.starts_with(MAGIC) {
+            return Err(Error::MagicIsUnsupported { path });
+        }
+
+        let (_, lib_count) = Self::parse_header(&map, byte_order)
+            .map_err(|r| Error::from_nom_parse_header(r, &map, &path))?;
 
         Ok(Self {
-            path: path.into(),
-            map,
+            path,
+            map: Arc::new(map),
+            byte_order,
             lib_count,
         })
     }
 
-    fn parse_header(bytes: &[u8]) -> IResult<&[u8], u32> {
+    /// Return the path of the cache file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Decode all entries of the cache, and of the cache it embeds in the new format, if any,
+    /// failing at the first invalid one.
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.iter()?.try_for_each(|entry| entry.map(|_| ()))?;
+        self.new_format()?.map_or(Ok(()), |cache| cache.validate())
+    }
+
+    /// Return the byte order of the cache file.
+    #[must_use]
+    pub fn byte_order(&self) -> ByteOrder {
+        ByteOrder::from_endianness(self.byte_order)
+    }
+
+    /// Return the byte order of the specified bytes if they are a cache in this format,
+    /// and whether they embed a cache in the `glibc-ld.so.cache1.1` format.
+    pub(crate) fn detect(bytes: &[u8]) -> Option<(ByteOrder, bool)> {
+        if !bytes.starts_with(MAGIC) {
+            return None;
+        }
+
+        let byte_order = Self::detect_byte_order(bytes);
+        let embeds_new_format = Self::new_format_offset(bytes, byte_order).is_some();
+        Some((ByteOrder::from_endianness(byte_order), embeds_new_format))
+    }
+
+    /// Return the byte order in which the specified bytes are a plausible cache.
+    ///
+    /// A byte order is plausible if the number of entries fits in the file,
+    /// and if all offsets of strings point into the string table.
+    pub(crate) fn detect_byte_order(bytes: &[u8]) -> Endianness {
+        let native = ByteOrder::NATIVE;
+        [native, native.swapped()]
+            .into_iter()
+            .map(ByteOrder::endianness)
+            .find(|&byte_order| Self::is_plausible(bytes, byte_order))
+            .unwrap_or(Endianness::Native)
+    }
+
+    fn is_plausible(bytes: &[u8], byte_order: Endianness) -> bool {
+        let Ok((input, lib_count)) = Self::parse_header(bytes, byte_order) else {
+            return false;
+        };
+
+        let entries_size = size_of::<Entry>().saturating_mul(lib_count as usize);
+        let (entries_bytes, string_table) = input.split_at(entries_size);
+
+        let string_table_size = string_table.len() as u64;
+        entries_bytes.chunks_exact(size_of::<Entry>()).all(|entry| {
+            match Self::parse_entry(entry, byte_order) {
+                Ok((_, (key, value))) => {
+                    u64::from(key) < string_table_size && u64::from(value) < string_table_size
+                }
+                Err(_) => false,
+            }
+        })
+    }
+
+    fn parse_header(bytes: &[u8], byte_order: Endianness) -> IResult<&[u8], u32> {
         assert_eq_size!(u32, c_uint);
 
         let (input, lib_count) = nom_preceded(
@@ -84,7 +224,7 @@ impl Cache {
                 nom_tag(MAGIC),
                 nom_take(offset_of!(Header, lib_count) - MAGIC.len()),
             ),
-            nom_u32(Endianness::Native),
+            nom_u32(byte_order),
         )(bytes)?;
 
         let max_lib_count = bytes
@@ -106,6 +246,50 @@ impl Cache {
         Ok((input, lib_count))
     }
 
+    fn parse_entry(bytes: &[u8], byte_order: Endianness) -> IResult<&[u8], (u32, u32)> {
+        nom_tuple((
+            nom_preceded(nom_take(offset_of!(Entry, key)), nom_u32(byte_order)),
+            nom_u32(byte_order),
+        ))(bytes)
+    }
+
+    /// Return the offset of the `glibc-ld.so.cache1.1` data embedded in the specified bytes,
+    /// if they are in the `ld.so-1.7.0` format, and if such data is present.
+    pub(crate) fn new_format_offset(bytes: &[u8], byte_order: Endianness) -> Option<usize> {
+        let (_, lib_count) = Self::parse_header(bytes, byte_order).ok()?;
+
+        let entries_end = size_of::<Header>()
+            .saturating_add(size_of::<Entry>().saturating_mul(lib_count as usize));
+
+        // The embedded data is aligned for its own entries, which contain 64-bits fields.
+        // Such fields are aligned on 4 bytes on some 32-bits architectures.
+        [align_of::<u64>(), align_of::<u32>()]
+            .into_iter()
+            .map(|align| entries_end.next_multiple_of(align))
+            .find(|&offset| {
+                bytes
+                    .get(offset..)
+                    .is_some_and(|bytes| bytes.starts_with(glibc_ld_so_cache_1dot1::MAGIC))
+            })
+    }
+
+    /// Return the cache in the `glibc-ld.so.cache1.1` format that is embedded in this cache,
+    /// if any.
+    ///
+    /// The GNU/Linux dynamic loader prefers the embedded cache, when it is present,
+    /// because it records more information about each shared library.
+    pub fn new_format(&self) -> Result<Option<glibc_ld_so_cache_1dot1::Cache>> {
+        Self::new_format_offset(&self.map, self.byte_order)
+            .map(|offset| {
+                glibc_ld_so_cache_1dot1::Cache::from_map(
+                    self.path.clone(),
+                    Arc::clone(&self.map),
+                    offset,
+                )
+            })
+            .transpose()
+    }
+
     /// Return an iterator that returns cache entries.
     pub fn iter(&self) -> Result<impl FusedIterator<Item = Result<crate::Entry<'_>>> + '_> {
         let entries_end = size_of::<Header>()
@@ -116,6 +300,8 @@ impl Cache {
             path: &self.path,
             entries_bytes,
             string_table: &self.map[entries_end..],
+            file_bytes: &self.map,
+            byte_order: self.byte_order,
         })
     }
 }
@@ -134,18 +320,14 @@ struct Iter<'cache> {
     path: &'cache Path,
     entries_bytes: &'cache [u8],
     string_table: &'cache [u8],
+    file_bytes: &'cache [u8],
+    byte_order: Endianness,
 }
 
 impl<'cache> Iter<'cache> {
     fn next_fallible(&mut self) -> Result<crate::Entry<'cache>> {
-        let (input, (key, value)) = nom_tuple((
-            nom_preceded(
-                nom_take(offset_of!(Entry, key)),
-                nom_u32(Endianness::Native),
-            ),
-            nom_u32(Endianness::Native),
-        ))(self.entries_bytes)
-        .map_err(|r| Error::from_nom_parse(r, self.entries_bytes, self.path))?;
+        let (input, (key, value)) = Cache::parse_entry(self.entries_bytes, self.byte_order)
+            .map_err(|r| Error::from_nom_parse(r, self.file_bytes, self.path))?;
 
         self.entries_bytes = input;
 
@@ -165,7 +347,13 @@ impl<'cache> Iter<'cache> {
             })?;
         let value = CStr::from_bytes_until_nul(value)?;
 
-        cstr_entry_to_crate_entry(key, value)
+        cstr_entry_to_crate_entry(
+            key,
+            value,
+            EntryMetadata::None,
+            CacheFormat::LdSO1dot7,
+            self.path,
+        )
     }
 }
 
@@ -1,9 +1,13 @@
 use core::mem::{offset_of, size_of};
+use std::ffi::OsStr;
 use std::io::{Cursor, Write};
+use std::path::Path;
 
+use nom::number::Endianness;
 use proptest::prelude::*;
 
 use super::{Cache, Entry, Header, MAGIC};
+use crate::{ByteOrder, LoadStrategy};
 
 fn print_cache(cache: &Cache) {
     for e in cache.iter().unwrap() {
@@ -28,9 +32,77 @@ fn load_compat() {
     print_cache(&cache);
 }
 
+#[test]
+fn new_format() {
+    let cache = Cache::load("tests/ld.so-1.7.0/ld.so.cache").unwrap();
+    assert!(cache.new_format().unwrap().is_none());
+
+    let cache = Cache::load("tests/ld.so-1.7.0/ld.so.cache.compat").unwrap();
+    let new_cache = cache.new_format().unwrap().unwrap();
+    assert_eq!(
+        new_cache.iter().unwrap().count(),
+        cache.iter().unwrap().count()
+    );
+
+    let entry = new_cache.iter().unwrap().next().unwrap().unwrap();
+    assert_eq!(entry.file_name, OsStr::new("lib2geom.so.1.2.0"));
+    assert_eq!(
+        entry.full_path,
+        Path::new("/lib/x86_64-linux-gnu/lib2geom.so.1.2.0")
+    );
+}
+
+#[test]
+fn load_byte_orders() {
+    for (name, byte_order, lib_dir) in [
+        ("le32", ByteOrder::LittleEndian, "/lib/i386-linux-gnu"),
+        ("le64", ByteOrder::LittleEndian, "/lib/x86_64-linux-gnu"),
+        ("be32", ByteOrder::BigEndian, "/lib/powerpc-linux-gnu"),
+        ("be64", ByteOrder::BigEndian, "/lib/powerpc64-linux-gnu"),
+    ] {
+        let path = format!("tests/ld.so-1.7.0/ld.so.cache.compat-{name}");
+
+        let cache = Cache::load(&path).unwrap();
+        assert_eq!(cache.byte_order(), byte_order);
+        let entries: Vec<_> = cache.iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].file_name, OsStr::new("libz.so.1"));
+        assert_eq!(
+            entries[0].full_path,
+            Path::new(lib_dir).join("libz.so.1").as_path()
+        );
+
+        let new_cache = cache.new_format().unwrap().unwrap();
+        let new_entries: Vec<_> = new_cache.iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(new_entries.len(), 3);
+        assert_eq!(new_entries[2].file_name, OsStr::new("libc.so.6"));
+        assert_eq!(
+            new_entries[2].full_path,
+            Path::new(lib_dir).join("libc.so.6").as_path()
+        );
+
+        for strategy in [
+            LoadStrategy::Map,
+            LoadStrategy::MapVerified,
+            LoadStrategy::Read,
+        ] {
+            let cache = Cache::load_with_byte_order(&path, byte_order, strategy).unwrap();
+            assert_eq!(cache.byte_order(), byte_order);
+            assert_eq!(cache.iter().unwrap().filter(Result::is_err).count(), 0);
+            assert_eq!(
+                cache.new_format().unwrap().unwrap().iter().unwrap().count(),
+                3
+            );
+
+            Cache::load_with_byte_order(&path, byte_order.swapped(), strategy).unwrap_err();
+        }
+    }
+}
+
 #[test]
 fn parse_byte_order_empty() {
-    Cache::parse_header(&[]).unwrap_err();
+    Cache::parse_header(&[], Endianness::Little).unwrap_err();
+    Cache::parse_header(&[], Endianness::Big).unwrap_err();
 }
 
 prop_compose! {
@@ -57,29 +129,63 @@ proptest! {
         random_u8 in proptest::num::u8::ANY,
         random_u32 in proptest::num::u32::ANY,
     ) {
-        load_random0([random_u8], lib_count, &bytes)?;
-        load_random0([random_u8], random_u32, &bytes)?;
+        for byte_order in [Endianness::Little, Endianness::Big] {
+            load_random0([random_u8], lib_count, &bytes, byte_order)?;
+            load_random0([random_u8], random_u32, &bytes, byte_order)?;
+        }
     }
 }
 
-fn load_random0(padding: [u8; 1], lib_count: u32, bytes: &[u8]) -> Result<(), TestCaseError> {
+fn load_random0(
+    padding: [u8; 1],
+    lib_count: u32,
+    bytes: &[u8],
+    byte_order: Endianness,
+) -> Result<(), TestCaseError> {
+    let u32_bytes: fn(u32) -> [u8; 4] = match byte_order {
+        Endianness::Big => u32::to_be_bytes,
+        Endianness::Little => u32::to_le_bytes,
+        Endianness::Native => u32::to_ne_bytes,
+    };
+
     let mut cursor = Cursor::new(Vec::<u8>::with_capacity(size_of::<Header>() + bytes.len()));
     cursor.write_all(MAGIC)?;
     cursor.write_all(&padding)?;
-    cursor.write_all(&lib_count.to_ne_bytes())?;
+    cursor.write_all(&u32_bytes(lib_count))?;
     cursor.write_all(bytes)?;
     let bytes = cursor.into_inner();
 
-    let Ok((_, lib_count)) = Cache::parse_header(&bytes) else {
+    // Detection must never panic, whatever the input.
+    let _ = Cache::detect_byte_order(&bytes);
+
+    let Ok((_, lib_count)) = Cache::parse_header(&bytes, byte_order) else {
         return Ok(());
     };
 
     let lib_count_bytes = bytes
         .get(offset_of!(Header, lib_count)..(offset_of!(Header, lib_count) + 4))
         .unwrap_or(&[]);
-    prop_assert_eq!(lib_count_bytes, lib_count.to_ne_bytes());
+    prop_assert_eq!(lib_count_bytes, u32_bytes(lib_count));
 
     // TODO(KAT): Test iterators.
 
     Ok(())
 }
+
+#[test]
+fn from_bytes() {
+    let path = "tests/ld.so-1.7.0/ld.so.cache.compat-be64";
+    let bytes: std::sync::Arc<[u8]> = std::fs::read(path).unwrap().into();
+
+    let cache = Cache::from_bytes_with_path(bytes, path).unwrap();
+    let expected = Cache::load(path).unwrap();
+    assert_eq!(cache.byte_order(), expected.byte_order());
+    assert_eq!(
+        cache.iter().unwrap().count(),
+        expected.iter().unwrap().count()
+    );
+
+    let new_cache = cache.new_format().unwrap().unwrap();
+    assert_eq!(new_cache.path(), Path::new(path));
+    assert!(new_cache.iter().unwrap().all(|e| e.is_ok()));
+}
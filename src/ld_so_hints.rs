@@ -9,30 +9,36 @@
 #[cfg(test)]
 mod tests;
 
-use core::ffi::{c_int, CStr};
+use core::ffi::{c_char, c_int, CStr};
 use core::iter::FusedIterator;
-use core::mem::{offset_of, size_of};
+use core::mem::size_of;
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt;
 use std::path::{Path, PathBuf};
 
-use memmap2::Mmap;
+use arrayvec::ArrayVec;
 use nom::bytes::complete::{tag as nom_tag, take as nom_take};
 use nom::combinator::peek as nom_peek;
-use nom::number::complete::{u32 as nom_u32, u64 as nom_u64};
+use nom::multi::fill as nom_fill;
+use nom::number::complete::{i32 as nom_i32, u32 as nom_u32, u64 as nom_u64};
 use nom::number::Endianness;
 use nom::sequence::{preceded as nom_preceded, terminated as nom_terminated, tuple as nom_tuple};
 use nom::IResult;
 use static_assertions::assert_eq_size;
 
-use crate::utils::{cstr_entry_to_crate_entry, map_file};
-use crate::{CacheProvider, DataModel, Error, Result};
+use crate::utils::{
+    cow_os_str_from_cstr, cstr_entry_to_crate_entry, load_file, path_from_bytes, CacheBytes,
+};
+use crate::{ByteOrder, CacheFormat, CacheProvider, DataModel, Error, LoadStrategy, Result};
 
-static CACHE_FILE_PATH: &str = "/var/run/ld.so.hints";
+pub(crate) static CACHE_FILE_PATH: &str = "/var/run/ld.so.hints";
 
 const MAGIC: u32 = 0x4c_44_48_69_u32;
 const MAGIC_LE32: [u8; 4] = MAGIC.to_le_bytes();
 const MAGIC_BE32: [u8; 4] = MAGIC.to_be_bytes();
 const MAGIC_LE64: [u8; 8] = (MAGIC as u64).to_le_bytes();
-const MAGIC_BE64: [u8; 8] = (MAGIC as u64).to_le_bytes();
+const MAGIC_BE64: [u8; 8] = (MAGIC as u64).to_be_bytes();
 
 //const VERSION_1: u32 = 1; // We do not support this ancient version.
 
@@ -40,7 +46,7 @@ const VERSION_2: u32 = 2;
 const VERSION_2_LE32: [u8; 4] = VERSION_2.to_le_bytes();
 const VERSION_2_BE32: [u8; 4] = VERSION_2.to_be_bytes();
 const VERSION_2_LE64: [u8; 8] = (VERSION_2 as u64).to_le_bytes();
-const VERSION_2_BE64: [u8; 8] = (VERSION_2 as u64).to_le_bytes();
+const VERSION_2_BE64: [u8; 8] = (VERSION_2 as u64).to_be_bytes();
 
 /// Maximum number of recognized shared object version numbers.
 const MAX_DEWEY: usize = 8;
@@ -84,6 +90,82 @@ struct Bucket {
 
 type ParseHeaderImplData = (usize, usize, usize, usize, usize, usize);
 
+/// Index of the name, index of the full path, version, and index of the next bucket.
+type ParseBucketData = (u32, u32, Version, c_int);
+
+/// Information about a cache entry, specific to the `ld.so.hints` format.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct EntryMetadata<'cache> {
+    /// Name of the shared library, as recorded in the hash table of the cache,
+    /// *i.e.*, without its `lib` prefix, `.so` suffix and version, *e.g.*, `c` for `libc.so.97.1`.
+    pub name: Cow<'cache, OsStr>,
+    /// Version of the shared library.
+    pub version: Version,
+}
+
+impl EntryMetadata<'_> {
+    /// Return information that owns its data, and therefore does not borrow from the cache.
+    #[must_use]
+    pub fn into_owned(self) -> EntryMetadata<'static> {
+        EntryMetadata {
+            name: Cow::Owned(self.name.into_owned()),
+            version: self.version,
+        }
+    }
+}
+
+/// Version of a shared library, *e.g.*, `97.1` for `libc.so.97.1`.
+///
+/// A version is made of up to 8 numbers, the first of which is the major version,
+/// and the second is the minor version.
+/// Versions are ordered by comparing their numbers from left to right,
+/// similarly to the dynamic loader.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Version(ArrayVec<c_int, MAX_DEWEY>);
+
+impl Version {
+    /// Create a version from its numbers.
+    ///
+    /// Returns `None` if more than 8 numbers are specified.
+    #[must_use]
+    pub fn new(numbers: &[c_int]) -> Option<Self> {
+        ArrayVec::try_from(numbers).ok().map(Self)
+    }
+
+    /// Return the numbers of this version.
+    #[must_use]
+    pub fn as_slice(&self) -> &[c_int] {
+        &self.0
+    }
+
+    /// Return the major version, if any.
+    #[must_use]
+    pub fn major(&self) -> Option<c_int> {
+        self.0.first().copied()
+    }
+
+    /// Return the minor version, if any.
+    #[must_use]
+    pub fn minor(&self) -> Option<c_int> {
+        self.0.get(1).copied()
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, number) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{number}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Cache of the OpenBSD or NetBSD dynamic loader.
 ///
 /// This loads a dynamic loader cache file (*e.g.*, `/var/run/ld.so.hints`),
@@ -91,12 +173,13 @@ type ParseHeaderImplData = (usize, usize, usize, usize, usize, usize);
 #[derive(Debug)]
 pub struct Cache {
     path: PathBuf,
-    map: Mmap,
+    map: CacheBytes,
     byte_order: Endianness,
     hash_table: usize,
     bucket_count: usize,
     string_table: usize,
     string_table_size: usize,
+    dir_list: usize,
 }
 
 impl Cache {
@@ -107,25 +190,66 @@ impl Cache {
 
     /// Create a cache that loads the specified cache file.
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::load_with_strategy(path, LoadStrategy::default())
+    }
+
+    /// Create a cache that loads the specified cache file, according to `strategy`.
+    pub fn load_with_strategy(path: impl AsRef<Path>, strategy: LoadStrategy) -> Result<Self> {
         let path = path.as_ref();
-        let map = map_file(path)?;
-        let (_, (data_model, byte_order)) =
-            Self::parse_byte_order(&map).map_err(|r| Error::from_nom_parse(r, &map, path))?;
-        let (_, (hash_table, bucket_count, string_table, string_table_size)) =
+        load_file(
+            path,
+            strategy,
+            |map| Self::from_cache_bytes(path.into(), map),
+            Self::validate,
+        )
+    }
+
+    /// Create a cache that parses the specified bytes, instead of loading a file.
+    ///
+    /// The bytes can be owned by any type that can be viewed as `&[u8]`, *e.g.*, `Vec<u8>`,
+    /// `Arc<[u8]>` or `&'static [u8]`.
+    /// The path of the cache is empty. See [`Self::from_bytes_with_path()`].
+    pub fn from_bytes(bytes: impl AsRef<[u8]> + Send + Sync + 'static) -> Result<Self> {
+        Self::from_bytes_with_path(bytes, PathBuf::default())
+    }
+
+    /// Create a cache that parses the specified bytes, instead of loading a file.
+    ///
+    /// `path` is returned by [`Self::path()`] and reported in errors, but it is not accessed.
+    pub fn from_bytes_with_path(
+        bytes: impl AsRef<[u8]> + Send + Sync + 'static,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self> {
+        Self::from_cache_bytes(path.into(), CacheBytes::from_owner(bytes))
+    }
+
+    pub(crate) fn from_cache_bytes(path: PathBuf, map: CacheBytes) -> Result<Self> {
+        let Ok((_, (data_model, byte_order))) = Self::parse_byte_order(&map) else {
+            return Err(Error::MagicIsUnsupported { path });
+        };
+        let (_, (hash_table, bucket_count, string_table, string_table_size, dir_list)) =
             Self::parse_header(&map, data_model, byte_order)
-                .map_err(|r| Error::from_nom_parse(r, &map, path))?;
+                .map_err(|r| Error::from_nom_parse_header(r, &map, &path))?;
 
         Ok(Self {
-            path: path.into(),
+            path,
             map,
             byte_order,
             hash_table,
             bucket_count,
             string_table,
             string_table_size,
+            dir_list,
         })
     }
 
+    /// Return the data model and byte order of the specified bytes if they are a cache
+    /// in this format.
+    pub(crate) fn detect(bytes: &[u8]) -> Option<(DataModel, ByteOrder)> {
+        let (_, (data_model, byte_order)) = Self::parse_byte_order(bytes).ok()?;
+        Some((data_model, ByteOrder::from_endianness(byte_order)))
+    }
+
     fn parse_byte_order(bytes: &[u8]) -> IResult<&[u8], (DataModel, Endianness)> {
         let nom_tag_long = nom_tag::<&[u8], &[u8], nom::error::Error<&[u8]>>;
 
@@ -151,12 +275,12 @@ impl Cache {
         bytes: &[u8],
         data_model: DataModel,
         byte_order: Endianness,
-    ) -> IResult<&[u8], (usize, usize, usize, usize)> {
+    ) -> IResult<&[u8], (usize, usize, usize, usize, usize)> {
         assert_eq_size!(u32, c_int);
 
         let (
             input,
-            (hash_table, bucket_count, string_table, string_table_size, end_of_hints, _dir_list),
+            (hash_table, bucket_count, string_table, string_table_size, end_of_hints, dir_list),
         ) = match data_model {
             DataModel::ILP32 => Self::parse_header_impl(bytes, nom_u32(byte_order)),
             DataModel::LP64 => Self::parse_header_impl(bytes, nom_u64(byte_order)),
@@ -168,9 +292,22 @@ impl Cache {
         let min_size = usize::max(usize::max(hash_table_end, string_table_end), end_of_hints);
         nom_peek(nom_take(min_size))(bytes)?;
 
+        if dir_list >= string_table_size {
+            return Err(nom::Err::Error(nom::error::make_error(
+                bytes,
+                nom::error::ErrorKind::Verify,
+            )));
+        }
+
         Ok((
             input,
-            (hash_table, bucket_count, string_table, string_table_size),
+            (
+                hash_table,
+                bucket_count,
+                string_table,
+                string_table_size,
+                dir_list,
+            ),
         ))
     }
 
@@ -215,23 +352,185 @@ impl Cache {
         ))
     }
 
+    fn parse_bucket(bytes: &[u8], byte_order: Endianness) -> IResult<&[u8], ParseBucketData> {
+        let mut dewey = [c_int::default(); MAX_DEWEY];
+        let (input, (name_index, path_index, (), dewey_count, next)) = nom_tuple((
+            nom_u32(byte_order),
+            nom_u32(byte_order),
+            nom_fill(nom_i32(byte_order), &mut dewey),
+            nom_i32(byte_order),
+            nom_i32(byte_order),
+        ))(bytes)?;
+
+        let dewey = usize::try_from(dewey_count)
+            .ok()
+            .and_then(|dewey_count| dewey.get(..dewey_count))
+            .ok_or_else(|| {
+                let err = nom::error::make_error(bytes, nom::error::ErrorKind::TooLarge);
+                nom::Err::Error(err)
+            })?;
+
+        let version = Version(dewey.try_into().unwrap_or_default());
+        Ok((input, (name_index, path_index, version, next)))
+    }
+
+    /// Return the path of the cache file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Decode all entries and directories of the cache, failing at the first invalid one.
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.iter()?.try_for_each(|entry| entry.map(|_| ()))?;
+        self.directories()?
+            .try_for_each(|directory| directory.map(|_| ()))
+    }
+
     /// Return an iterator that returns cache entries.
+    ///
+    /// Unused buckets of the hash table are skipped.
     pub fn iter(&self) -> Result<impl FusedIterator<Item = Result<crate::Entry<'_>>> + '_> {
+        Ok(Iter {
+            path: &self.path,
+            hash_table: self.hash_table_bytes(),
+            string_table: self.string_table_bytes(),
+            file_bytes: &self.map,
+            byte_order: self.byte_order,
+        })
+    }
+
+    /// Return an iterator that returns the directories scanned by `ldconfig` to build the cache.
+    ///
+    /// These are the directories listed in the header of the cache, in their original order.
+    pub fn directories(&self) -> Result<impl FusedIterator<Item = Result<Cow<'_, Path>>> + '_> {
+        let bytes =
+            self.string_table_bytes()
+                .get(self.dir_list..)
+                .ok_or(Error::OffsetIsInvalid {
+                    path: self.path.clone(),
+                })?;
+
+        let iter = CStr::from_bytes_until_nul(bytes)?
+            .to_bytes()
+            .split(|&b| b == b':')
+            .filter(|bytes| !bytes.is_empty())
+            .map(path_from_bytes);
+
+        Ok(iter)
+    }
+
+    /// Return the entry of the shared library named `name`, with the major version `major`.
+    ///
+    /// `name` is the name of the library without its `lib` prefix and `.so` suffix,
+    /// *e.g.*, `c` for `libc.so.97.1`.
+    ///
+    /// Similarly to the dynamic loader, this follows the chain of buckets of the hash table
+    /// that starts at the hash of `name` and `major`.
+    /// If many entries match, then the one with the highest version is returned.
+    pub fn lookup(
+        &self,
+        name: impl AsRef<OsStr>,
+        major: c_int,
+    ) -> Result<Option<crate::Entry<'_>>> {
+        let name = name.as_ref().as_encoded_bytes();
+        let hash_table = self.hash_table_bytes();
+        let string_table = self.string_table_bytes();
+
+        if self.bucket_count == 0 {
+            return Ok(None);
+        }
+
+        let mut best: Option<(u32, u32, Version)> = None;
+        let mut index = (hint_hash(name, major) as usize) % self.bucket_count;
+
+        // Each bucket is visited at most once, even if the chain loops.
+        for _ in 0..self.bucket_count {
+            let bucket = &hash_table[index.saturating_mul(size_of::<Bucket>())..];
+            let (_, (key, value, version, next)) = Self::parse_bucket(bucket, self.byte_order)
+                .map_err(|r| Error::from_nom_parse(r, &self.map, &self.path))?;
+
+            let key_bytes = string_table
+                .get((key as usize)..)
+                .ok_or(Error::OffsetIsInvalid {
+                    path: self.path.clone(),
+                })?;
+
+            if value != 0
+                && CStr::from_bytes_until_nul(key_bytes)?.to_bytes() == name
+                && version.major() == Some(major)
+                && best.as_ref().map_or(true, |(_, _, best)| version > *best)
+            {
+                best = Some((key, value, version));
+            }
+
+            match usize::try_from(next) {
+                Ok(next) if next < self.bucket_count => index = next,
+                _ => break,
+            }
+        }
+
+        best.map(|(key, value, version)| {
+            bucket_to_entry(&self.path, string_table, key, value, version)
+        })
+        .transpose()
+    }
+
+    fn hash_table_bytes(&self) -> &[u8] {
         let hash_table_end = self
             .hash_table
             .saturating_add(self.bucket_count.saturating_mul(size_of::<Bucket>()));
-        let hash_table = &self.map[self.hash_table..hash_table_end];
+        &self.map[self.hash_table..hash_table_end]
+    }
 
+    fn string_table_bytes(&self) -> &[u8] {
         let string_table_end = self.string_table.saturating_add(self.string_table_size);
-        let string_table = &self.map[self.string_table..string_table_end];
+        &self.map[self.string_table..string_table_end]
+    }
+}
 
-        Ok(Iter {
-            path: &self.path,
-            hash_table,
-            string_table,
-            byte_order: self.byte_order,
-        })
+/// Hash function of the names of shared libraries, used by `ldconfig` and the dynamic loader.
+fn hint_hash(name: &[u8], major: c_int) -> c_int {
+    let mut k: c_int = 0;
+    for &c in name {
+        let c = c_int::from(c_char::from_ne_bytes([c]));
+        k = (((k << 1_u32) + (k >> 14_u32)) ^ c) & 0x3fff_i32;
     }
+    (((k << 1_u32) + (k >> 14_u32)) ^ major.wrapping_mul(257_i32)) & 0x3fff_i32
+}
+
+fn bucket_to_entry<'cache>(
+    path: &'cache Path,
+    string_table: &'cache [u8],
+    key: u32,
+    value: u32,
+    version: Version,
+) -> Result<crate::Entry<'cache>> {
+    let key = string_table
+        .get((key as usize)..)
+        .ok_or(Error::OffsetIsInvalid { path: path.into() })?;
+    let key = CStr::from_bytes_until_nul(key)?;
+
+    let value = string_table
+        .get((value as usize)..)
+        .ok_or(Error::OffsetIsInvalid { path: path.into() })?;
+    let value = CStr::from_bytes_until_nul(value)?;
+
+    let name = cow_os_str_from_cstr(key)?;
+    let metadata = crate::EntryMetadata::LdSOHints(EntryMetadata { name, version });
+    let mut entry = cstr_entry_to_crate_entry(key, value, metadata, CacheFormat::LdSOHints, path)?;
+
+    // The key is the name of the library without its prefix, suffix and version, *e.g.*, `c`,
+    // so the file name is taken from the full path instead, *e.g.*, `libc.so.97.1`.
+    // The key remains available as `EntryMetadata::name`.
+    let file_name = match &entry.full_path {
+        Cow::Borrowed(full_path) => full_path.file_name().map(Cow::Borrowed),
+        Cow::Owned(full_path) => full_path.file_name().map(|name| Cow::Owned(name.into())),
+    };
+    if let Some(file_name) = file_name {
+        entry.file_name = file_name;
+    }
+    Ok(entry)
 }
 
 impl CacheProvider for Cache {
@@ -241,6 +540,56 @@ impl CacheProvider for Cache {
         let iter = self.iter()?;
         Ok(Box::new(iter))
     }
+
+    /// Return the entry of the shared library file named `name`, *e.g.*, `libc.so.97.1`,
+    /// through the hash table.
+    ///
+    /// Similarly to the dynamic loader, the entry has the same major version, and a minor version
+    /// that is not lower than the one requested, so its file name might differ from `name`,
+    /// *e.g.*, `libc.so.97.2`.
+    /// Names that are not of the form `lib<name>.so.<major>[.<minor>]` are compared with the
+    /// file names of all entries.
+    fn lookup<'cache>(
+        &'cache self,
+        name: &OsStr,
+    ) -> Result<Box<dyn FusedIterator<Item = Result<crate::Entry<'cache>>> + 'cache>> {
+        let Some((lib_name, version)) = parse_file_name(name.as_encoded_bytes()) else {
+            let name = name.to_os_string();
+            let iter = self.iter()?.filter(move |entry| {
+                entry
+                    .as_ref()
+                    .map_or(true, |entry| *entry.file_name == *name)
+            });
+            return Ok(Box::new(iter));
+        };
+
+        let major = version.major().unwrap_or_default();
+        let lib_name = path_from_bytes(lib_name)?;
+        let entry = self.lookup(lib_name.as_os_str(), major)?.filter(|entry| {
+            let crate::EntryMetadata::LdSOHints(metadata) = &entry.metadata else {
+                return false;
+            };
+            metadata.version.minor().unwrap_or_default() >= version.minor().unwrap_or_default()
+        });
+        Ok(Box::new(entry.map(Ok).into_iter()))
+    }
+}
+
+/// Parse a file name of the form `lib<name>.so.<major>[.<minor>...]`,
+/// and return the name and the version.
+fn parse_file_name(file_name: &[u8]) -> Option<(&[u8], Version)> {
+    let file_name = file_name.strip_prefix(b"lib")?;
+    let index = file_name.windows(4).position(|w| w == b".so.")?;
+    let (name, version) = (&file_name[..index], &file_name[index.saturating_add(4)..]);
+
+    let version = version
+        .split(|&c| c == b'.')
+        .map(|number| std::str::from_utf8(number).ok()?.parse::<c_int>().ok())
+        .collect::<Option<Vec<c_int>>>()?;
+
+    (!name.is_empty())
+        .then(|| Version::new(&version).map(|version| (name, version)))
+        .flatten()
 }
 
 #[derive(Debug)]
@@ -248,39 +597,26 @@ struct Iter<'cache> {
     path: &'cache Path,
     hash_table: &'cache [u8],
     string_table: &'cache [u8],
+    file_bytes: &'cache [u8],
     byte_order: Endianness,
 }
 
 impl<'cache> Iter<'cache> {
-    fn next_fallible(&mut self) -> Result<crate::Entry<'cache>> {
-        let (input, (key, value)) = nom_tuple((
-            nom_u32(self.byte_order),
-            nom_terminated(
-                nom_u32(self.byte_order),
-                nom_take(size_of::<Bucket>() - offset_of!(Bucket, dewey)),
-            ),
-        ))(self.hash_table)
-        .map_err(|r| Error::from_nom_parse(r, self.hash_table, self.path))?;
-
+    /// Return the entry of the next bucket, or `None` if that bucket is unused.
+    fn next_fallible(&mut self) -> Result<Option<crate::Entry<'cache>>> {
+        let (bucket, input) = self.hash_table.split_at(size_of::<Bucket>());
         self.hash_table = input;
 
-        let key = self
-            .string_table
-            .get((key as usize)..)
-            .ok_or(Error::OffsetIsInvalid {
-                path: self.path.into(),
-            })?;
-        let key = CStr::from_bytes_until_nul(key)?;
+        let (_, (key, value, version, _next)) = Cache::parse_bucket(bucket, self.byte_order)
+            .map_err(|r| Error::from_nom_parse(r, self.file_bytes, self.path))?;
 
-        let value = self
-            .string_table
-            .get((value as usize)..)
-            .ok_or(Error::OffsetIsInvalid {
-                path: self.path.into(),
-            })?;
-        let value = CStr::from_bytes_until_nul(value)?;
+        // The string table always starts with the name of a library, so `ldconfig` marks
+        // unused buckets with a zero index of the full path.
+        if value == 0 {
+            return Ok(None);
+        }
 
-        cstr_entry_to_crate_entry(key, value)
+        bucket_to_entry(self.path, self.string_table, key, value, version).map(Some)
     }
 }
 
@@ -288,19 +624,18 @@ impl<'cache> Iterator for Iter<'cache> {
     type Item = Result<crate::Entry<'cache>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.hash_table.len() < size_of::<Bucket>() {
-            None
-        } else {
-            Some(self.next_fallible())
+        while self.hash_table.len() >= size_of::<Bucket>() {
+            if let Some(r) = self.next_fallible().transpose() {
+                return Some(r);
+            }
         }
+        None
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         let remaining = self.hash_table.len() / size_of::<Bucket>();
-        (remaining, Some(remaining))
+        (0, Some(remaining))
     }
 }
 
 impl<'cache> FusedIterator for Iter<'cache> {}
-
-impl<'cache> ExactSizeIterator for Iter<'cache> {}
@@ -1,4 +1,11 @@
-use super::Cache;
+use core::mem::{offset_of, size_of};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use nom::number::Endianness;
+
+use super::{parse_file_name, Bucket, Cache, Version};
+use crate::{CacheProvider, DataModel, EntryMetadata};
 
 fn print_cache(cache: &Cache) {
     for e in cache.iter().unwrap() {
@@ -17,7 +24,232 @@ fn load() {
     print_cache(&cache);
 }
 
+#[test]
+fn entry_versions() {
+    let cache = Cache::load("tests/ld.so.hints/ld.so.hints").unwrap();
+    let metadata = cache
+        .iter()
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|e| *e.file_name == *"libc.so.97.1")
+        .map(|e| match e.metadata {
+            EntryMetadata::LdSOHints(metadata) => metadata,
+            metadata => panic!("unexpected metadata: {metadata:?}"),
+        })
+        .unwrap();
+
+    // The name recorded in the hash table is the name of the library.
+    assert_eq!(metadata.name, OsStr::new("c"));
+
+    let version = metadata.version;
+    assert_eq!(version.as_slice(), [97_i32, 1_i32]);
+    assert_eq!(version.major(), Some(97_i32));
+    assert_eq!(version.minor(), Some(1_i32));
+    assert_eq!(version.to_string(), "97.1");
+}
+
+#[test]
+fn version_ordering() {
+    let v96_1 = Version::new(&[96_i32, 1_i32]).unwrap();
+    let v97_0 = Version::new(&[97_i32, 0_i32]).unwrap();
+    let v97 = Version::new(&[97_i32]).unwrap();
+    let v97_0_1 = Version::new(&[97_i32, 0_i32, 1_i32]).unwrap();
+
+    assert!(v96_1 < v97_0);
+    assert!(v97 < v97_0);
+    assert!(v97_0 < v97_0_1);
+    assert_eq!(Version::new(&[]).unwrap(), Version::default());
+    assert_eq!(Version::new(&[]).unwrap().major(), None);
+    assert_eq!(Version::new(&[1_i32; 9]), None);
+}
+
+#[test]
+fn parse_bucket_dewey_count_invalid() {
+    let mut bytes = [0_u8; size_of::<Bucket>()];
+    let dewey_count_offset = offset_of!(Bucket, dewey_count);
+
+    bytes[dewey_count_offset..(dewey_count_offset + 4)].copy_from_slice(&9_i32.to_le_bytes());
+    Cache::parse_bucket(&bytes, Endianness::Little).unwrap_err();
+
+    bytes[dewey_count_offset..(dewey_count_offset + 4)].copy_from_slice(&(-1_i32).to_le_bytes());
+    Cache::parse_bucket(&bytes, Endianness::Little).unwrap_err();
+
+    bytes[dewey_count_offset..(dewey_count_offset + 4)].copy_from_slice(&8_i32.to_le_bytes());
+    let (_, (_, _, version, _)) = Cache::parse_bucket(&bytes, Endianness::Little).unwrap();
+    assert_eq!(version.as_slice(), [0_i32; 8]);
+}
+
+#[test]
+fn iter_skips_unused_buckets() {
+    let cache = Cache::load("tests/ld.so.hints/ld.so.hints-be32").unwrap();
+    let names: Vec<_> = cache
+        .iter()
+        .unwrap()
+        .map(|e| e.unwrap().full_path.into_owned())
+        .collect();
+
+    assert_eq!(
+        names,
+        [
+            "/usr/lib/libm.so.10.1",
+            "/usr/lib/libkvm.so.4.2",
+            "/usr/local/lib/libevent.so.14.0",
+            "/usr/lib/libz.so.7.0",
+            "/usr/lib/libc.so.96.1",
+            "/usr/lib/libc.so.97.0",
+        ]
+        .map(PathBuf::from)
+    );
+}
+
+#[test]
+fn lookup() {
+    let cache = Cache::load("tests/ld.so.hints/ld.so.hints-be32").unwrap();
+
+    for (name, major, full_path) in [
+        ("c", 96_i32, "/usr/lib/libc.so.96.1"),
+        ("c", 97_i32, "/usr/lib/libc.so.97.0"),
+        ("kvm", 4_i32, "/usr/lib/libkvm.so.4.2"),
+        ("event", 14_i32, "/usr/local/lib/libevent.so.14.0"),
+        ("m", 10_i32, "/usr/lib/libm.so.10.1"),
+    ] {
+        let entry = cache.lookup(name, major).unwrap().unwrap();
+        assert_eq!(Some(&*entry.file_name), Path::new(full_path).file_name());
+        assert_eq!(entry.full_path, Path::new(full_path));
+    }
+
+    assert!(cache.lookup("c", 98).unwrap().is_none());
+    assert!(cache.lookup("kvm", 52).unwrap().is_none());
+    assert!(cache.lookup("libc", 97).unwrap().is_none());
+}
+
+#[test]
+fn lookup_matches_iter() {
+    let cache = Cache::load("tests/ld.so.hints/ld.so.hints").unwrap();
+
+    for entry in cache.iter().unwrap().map(Result::unwrap) {
+        let EntryMetadata::LdSOHints(metadata) = &entry.metadata else {
+            panic!("unexpected metadata: {:?}", entry.metadata);
+        };
+        let major = metadata.version.major().unwrap();
+        let (name, version) = parse_file_name(entry.file_name.as_encoded_bytes()).unwrap();
+        assert_eq!(version, metadata.version);
+        assert_eq!(name, metadata.name.as_encoded_bytes());
+
+        let name = std::str::from_utf8(name).unwrap();
+        let found = cache.lookup(name, major).unwrap().unwrap();
+        assert_eq!(found.full_path, entry.full_path);
+    }
+}
+
+#[test]
+fn lookup_file_name() {
+    let cache = Cache::load("tests/ld.so.hints/ld.so.hints-be32").unwrap();
+    let lookup = |name: &str| -> Vec<PathBuf> {
+        CacheProvider::lookup(&cache, OsStr::new(name))
+            .unwrap()
+            .map(|e| e.unwrap().full_path.into_owned())
+            .collect()
+    };
+
+    assert_eq!(lookup("libc.so.96"), [Path::new("/usr/lib/libc.so.96.1")]);
+    assert_eq!(lookup("libc.so.96.1"), [Path::new("/usr/lib/libc.so.96.1")]);
+    assert_eq!(lookup("libc.so.97.0"), [Path::new("/usr/lib/libc.so.97.0")]);
+    assert!(lookup("libc.so.97.1").is_empty());
+    assert!(lookup("libc.so.98").is_empty());
+    assert_eq!(
+        lookup("libevent.so.14"),
+        [Path::new("/usr/local/lib/libevent.so.14.0")]
+    );
+
+    // Other names are compared with the file names of the entries.
+    assert!(lookup("c").is_empty());
+    assert!(lookup("libc.so").is_empty());
+}
+
+#[test]
+fn parse_file_names() {
+    let version = |numbers: &[i32]| Version::new(numbers).unwrap();
+
+    assert_eq!(
+        parse_file_name(b"libc.so.97.1"),
+        Some((&b"c"[..], version(&[97_i32, 1_i32])))
+    );
+    assert_eq!(
+        parse_file_name(b"libstdc++.so.57"),
+        Some((&b"stdc++"[..], version(&[57_i32])))
+    );
+    assert_eq!(parse_file_name(b"libc.so"), None);
+    assert_eq!(parse_file_name(b"libc.so."), None);
+    assert_eq!(parse_file_name(b"lib.so.1"), None);
+    assert_eq!(parse_file_name(b"c.so.97"), None);
+    assert_eq!(parse_file_name(b"libc.so.97.x"), None);
+    assert_eq!(parse_file_name(b"libc.so.1.2.3.4.5.6.7.8.9"), None);
+}
+
+#[test]
+fn hint_hash() {
+    assert_eq!(super::hint_hash(b"c", 97_i32) % 147_i32, 89_i32);
+    assert_eq!(super::hint_hash(b"pthread", 27_i32) % 147_i32, 101_i32);
+}
+
+#[test]
+fn directories() {
+    let cache = Cache::load("tests/ld.so.hints/ld.so.hints").unwrap();
+    let directories: Vec<_> = cache.directories().unwrap().map(Result::unwrap).collect();
+    assert_eq!(
+        directories,
+        ["/usr/lib", "/usr/X11R6/lib", "/usr/local/lib"].map(Path::new)
+    );
+
+    let cache = Cache::load("tests/ld.so.hints/ld.so.hints-be32").unwrap();
+    let directories: Vec<_> = cache.directories().unwrap().map(Result::unwrap).collect();
+    assert_eq!(directories, ["/usr/lib", "/usr/local/lib"].map(Path::new));
+}
+
+#[test]
+fn parse_header_dir_list_invalid() {
+    let mut bytes = std::fs::read("tests/ld.so.hints/ld.so.hints-be32").unwrap();
+    assert!(Cache::parse_header(&bytes, DataModel::ILP32, Endianness::Big).is_ok());
+
+    // Point the directory list at the end of the string table.
+    let string_table_size: [u8; 4] = bytes[20..24].try_into().unwrap();
+    bytes[28..32].copy_from_slice(&string_table_size);
+    assert!(Cache::parse_header(&bytes, DataModel::ILP32, Endianness::Big).is_err());
+}
+
 #[test]
 fn parse_byte_order_empty() {
     Cache::parse_byte_order(&[]).unwrap_err();
 }
+
+#[test]
+fn big_endian_64() {
+    let entries = |path: &str| -> Vec<(OsString, PathBuf)> {
+        Cache::load(path)
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(|e| e.unwrap())
+            .map(|e| (e.file_name.into_owned(), e.full_path.into_owned()))
+            .collect()
+    };
+
+    let expected = entries("tests/ld.so.hints/ld.so.hints-be32");
+    assert!(!expected.is_empty());
+    assert_eq!(entries("tests/ld.so.hints/ld.so.hints-be64"), expected);
+}
+
+#[test]
+fn from_bytes() {
+    let path = "tests/ld.so.hints/ld.so.hints-be32";
+    let cache = Cache::from_bytes(std::fs::read(path).unwrap()).unwrap();
+    assert_eq!(cache.path(), Path::new(""));
+
+    let entry = cache.lookup("c", 97_i32).unwrap().unwrap();
+    assert_eq!(entry.full_path, Path::new("/usr/lib/libc.so.97.0"));
+    assert_eq!(
+        cache.iter().unwrap().count(),
+        Cache::load(path).unwrap().iter().unwrap().count()
+    );
+}
@@ -54,65 +54,409 @@
 )]
 */
 
+mod builder;
+mod detect;
 mod errors;
 pub mod glibc_ld_so_cache_1dot1;
 pub mod ld_elf_so_hints;
 pub mod ld_so_1dot7;
 pub mod ld_so_hints;
+pub mod libmap_conf;
 mod utils;
+#[cfg(all(feature = "watch", target_os = "linux"))]
+pub mod watch;
 
 use core::iter::FusedIterator;
 use core::mem::size_of;
 use std::borrow::Cow;
 use std::ffi::OsStr;
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use arrayvec::ArrayVec;
+use nom::number::Endianness;
 use static_assertions::const_assert;
 
+pub use crate::builder::{CacheBuilder, LoadOutcome, LoadReport};
+pub use crate::detect::{detect_format, detect_format_of, DetectedFormat};
 pub use crate::errors::Error;
-
-const CACHE_IMPL_COUNT: usize = 5;
+use crate::utils::{load_file, resolve_in_root, CacheBytes, FileVersion};
 
 /// Result of a fallible operation.
 pub type Result<T> = core::result::Result<T, Error>;
 
 /// Supported data models.
-/// See: https://en.wikipedia.org/wiki/64-bit_computing#64-bit_data_models
-#[derive(Debug, Clone, Copy)]
-enum DataModel {
+/// See: <https://en.wikipedia.org/wiki/64-bit_computing#64-bit_data_models>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataModel {
     /// c_int=i32 c_long=i32
     ILP32,
     /// c_int=i32 c_long=i64
     LP64,
 }
 
+/// Byte order of the data in a cache file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ByteOrder {
+    /// Least significant byte first.
+    LittleEndian,
+    /// Most significant byte first.
+    BigEndian,
+}
+
+impl ByteOrder {
+    /// Byte order of the architecture this crate was compiled for.
+    #[cfg(target_endian = "little")]
+    pub const NATIVE: Self = Self::LittleEndian;
+
+    /// Byte order of the architecture this crate was compiled for.
+    #[cfg(target_endian = "big")]
+    pub const NATIVE: Self = Self::BigEndian;
+
+    /// Return the opposite byte order.
+    #[must_use]
+    pub const fn swapped(self) -> Self {
+        match self {
+            Self::LittleEndian => Self::BigEndian,
+            Self::BigEndian => Self::LittleEndian,
+        }
+    }
+
+    pub(crate) const fn from_endianness(endianness: Endianness) -> Self {
+        match endianness {
+            Endianness::Big => Self::BigEndian,
+            Endianness::Little => Self::LittleEndian,
+            Endianness::Native => Self::NATIVE,
+        }
+    }
+
+    pub(crate) const fn endianness(self) -> Endianness {
+        match self {
+            Self::LittleEndian => Endianness::Little,
+            Self::BigEndian => Endianness::Big,
+        }
+    }
+}
+
+/// Class of an ELF file, *i.e.*, whether it targets a 32-bits or a 64-bits architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ElfClass {
+    /// 32-bits objects (`ELFCLASS32`).
+    Elf32,
+    /// 64-bits objects (`ELFCLASS64`).
+    Elf64,
+}
+
+impl ElfClass {
+    /// Class of the ELF files of the architecture this crate was compiled for.
+    #[cfg(target_pointer_width = "32")]
+    pub const NATIVE: Self = Self::Elf32;
+
+    /// Class of the ELF files of the architecture this crate was compiled for.
+    #[cfg(target_pointer_width = "64")]
+    pub const NATIVE: Self = Self::Elf64;
+}
+
+/// Strategy of loading cache files in memory.
+///
+/// A cache file mapped in memory is not copied, but the process receives the `SIGBUS` signal
+/// when it accesses a part of the file that was truncated after it was mapped.
+/// `ldconfig` replaces cache files atomically by renaming new files over them, which does not
+/// affect mapped files, but other tools might modify cache files in place.
+/// Only [`LoadStrategy::Read`] protects a cache that is kept after it is loaded from such
+/// modifications.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LoadStrategy {
+    /// Map the file in memory.
+    #[default]
+    Map,
+    /// Map the file in memory, decode all its entries, then verify that it was not modified
+    /// or replaced meanwhile, by comparing its device, inode, size and modification time.
+    ///
+    /// This fails with [`Error::FileChanged`] if the file changed.
+    /// The cache still reads the mapped file after it is loaded, so modifications after that
+    /// are neither detected nor prevented, and might affect the cache.
+    MapVerified,
+    /// Read the file into memory owned by the cache.
+    ///
+    /// Later modifications of the file do not affect the cache.
+    Read,
+}
+
+/// Format of a dynamic loader cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum CacheFormat {
+    /// `ld.so-1.7.0` format of the GNU/Linux dynamic loader (see [`ld_so_1dot7`]).
+    LdSO1dot7,
+    /// `glibc-ld.so.cache1.1` format of the GNU/Linux dynamic loader
+    /// (see [`glibc_ld_so_cache_1dot1`]).
+    GLibCLdSOCache1dot1,
+    /// `ld-elf.so.hints` format of the FreeBSD dynamic loader (see [`ld_elf_so_hints`]).
+    LdELFSOHints,
+    /// `ld.so.hints` format of the OpenBSD or NetBSD dynamic loader (see [`ld_so_hints`]).
+    LdSOHints,
+    /// Format of a custom cache provider registered by [`Cache::with_provider()`].
+    ///
+    /// Caches of this format are not loaded from files, so they are not recognized by
+    /// [`CacheBuilder`] or [`AnyCache`].
+    Custom,
+}
+
 /// Cache entry.
-#[derive(Debug)]
+///
+/// Entries borrow from the cache that returned them. See [`Entry::into_owned()`] in order to
+/// keep them after the cache is dropped.
+///
+/// With the `serde` feature, file names (*i.e.*, `OsStr` values) are serialized as `serde`
+/// serializes `OsStr`: as an enum whose `Unix` variant holds the bytes of the name on Unix,
+/// *e.g.*, `{"Unix":[108,105,98]}` in JSON, and whose `Windows` variant holds its UTF-16 code
+/// units on Windows. They can only be deserialized on the same family of systems.
+/// Paths are serialized as strings, which fails for paths that are not valid UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub struct Entry<'cache> {
     /// File name of the shared library.
     pub file_name: Cow<'cache, OsStr>,
     /// Absolute path of the shared library.
     pub full_path: Cow<'cache, Path>,
+    /// Information specific to the format of the cache that returned this entry.
+    pub metadata: EntryMetadata<'cache>,
+    /// Format of the cache that returned this entry.
+    pub cache_format: CacheFormat,
+    /// Path of the cache file that returned this entry.
+    pub cache_path: Cow<'cache, Path>,
+}
+
+impl<'cache> Entry<'cache> {
+    /// Create an entry of a shared library, without additional information.
+    ///
+    /// This is meant for custom cache providers (see [`CacheProvider`]),
+    /// which can then set [`Entry::metadata`].
+    pub fn new(
+        file_name: impl Into<Cow<'cache, OsStr>>,
+        full_path: impl Into<Cow<'cache, Path>>,
+        cache_format: CacheFormat,
+        cache_path: impl Into<Cow<'cache, Path>>,
+    ) -> Self {
+        Self {
+            file_name: file_name.into(),
+            full_path: full_path.into(),
+            metadata: EntryMetadata::None,
+            cache_format,
+            cache_path: cache_path.into(),
+        }
+    }
+
+    /// Return an entry that owns its data, and therefore does not borrow from the cache.
+    #[must_use]
+    pub fn into_owned(self) -> OwnedEntry {
+        Entry {
+            file_name: Cow::Owned(self.file_name.into_owned()),
+            full_path: Cow::Owned(self.full_path.into_owned()),
+            metadata: self.metadata.into_owned(),
+            cache_format: self.cache_format,
+            cache_path: Cow::Owned(self.cache_path.into_owned()),
+        }
+    }
+}
+
+/// Cache entry that owns its data. See [`Entry::into_owned()`].
+pub type OwnedEntry = Entry<'static>;
+
+/// Information about a cache entry, specific to the format of the cache that returned it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum EntryMetadata<'cache> {
+    /// The cache format does not record any additional information.
+    None,
+    /// Information recorded in a `glibc-ld.so.cache1.1` cache.
+    GLibCLdSOCache1dot1(glibc_ld_so_cache_1dot1::EntryMetadata<'cache>),
+    /// Information recorded in an `ld.so.hints` cache.
+    LdSOHints(ld_so_hints::EntryMetadata<'cache>),
+    /// Information recorded in an `ld-elf.so.hints` cache.
+    LdELFSOHints(ld_elf_so_hints::EntryMetadata),
 }
 
-trait CacheProvider: fmt::Debug + Sync + Send {
+impl EntryMetadata<'_> {
+    /// Return information that owns its data, and therefore does not borrow from the cache.
+    #[must_use]
+    pub fn into_owned(self) -> EntryMetadata<'static> {
+        match self {
+            Self::None => EntryMetadata::None,
+            Self::GLibCLdSOCache1dot1(metadata) => {
+                EntryMetadata::GLibCLdSOCache1dot1(metadata.into_owned())
+            }
+            Self::LdSOHints(metadata) => EntryMetadata::LdSOHints(metadata.into_owned()),
+            Self::LdELFSOHints(metadata) => EntryMetadata::LdELFSOHints(metadata),
+        }
+    }
+}
+
+/// Source of cache entries aggregated by a [`Cache`].
+///
+/// This is implemented by the caches of all supported formats, and can be implemented by
+/// custom sources of shared libraries, which are registered by [`Cache::with_provider()`].
+/// Entries returned by custom sources should be of the [`CacheFormat::Custom`] format.
+pub trait CacheProvider: fmt::Debug + Sync + Send {
+    /// Return an iterator that returns all entries.
     fn entries_iter<'cache>(
         &'cache self,
     ) -> Result<Box<dyn FusedIterator<Item = Result<Entry<'cache>>> + 'cache>>;
+
+    /// Return an iterator that returns the entries whose file name is `name`.
+    ///
+    /// By default, this scans all entries.
+    fn lookup<'cache>(
+        &'cache self,
+        name: &OsStr,
+    ) -> Result<Box<dyn FusedIterator<Item = Result<Entry<'cache>>> + 'cache>> {
+        let name = name.to_os_string();
+        let iter = self.entries_iter()?.filter(move |entry| {
+            entry
+                .as_ref()
+                .map_or(true, |entry| *entry.file_name == *name)
+        });
+        Ok(Box::new(iter))
+    }
 }
 
+/// Cache loaded in any of the supported formats.
 #[derive(Debug)]
-enum CacheImpl {
+#[non_exhaustive]
+pub enum AnyCache {
+    /// Cache in the `ld.so-1.7.0` format.
     LdSO1dot7(ld_so_1dot7::Cache),
+    /// Cache in the `glibc-ld.so.cache1.1` format.
     GLibCLdSOCache1dot1(glibc_ld_so_cache_1dot1::Cache),
+    /// Cache in the `ld-elf.so.hints` format.
     LdELFSOHints(ld_elf_so_hints::Cache),
+    /// Cache in the `ld.so.hints` format.
     LdSOHints(ld_so_hints::Cache),
 }
 
-impl AsRef<dyn CacheProvider> for CacheImpl {
+impl AnyCache {
+    /// Create a cache that loads the specified cache file, in the format detected by
+    /// [`detect_format()`].
+    ///
+    /// Compatibility-mode `ld.so-1.7.0` cache files are loaded in the `ld.so-1.7.0` format.
+    /// See [`ld_so_1dot7::Cache::new_format()`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::load_with_strategy(path, LoadStrategy::default())
+    }
+
+    /// Create a cache that loads the specified cache file, according to `strategy`.
+    ///
+    /// See [`Self::load()`].
+    pub fn load_with_strategy(path: impl AsRef<Path>, strategy: LoadStrategy) -> Result<Self> {
+        let path = path.as_ref();
+        load_file(
+            path,
+            strategy,
+            |map| Self::from_cache_bytes(path.into(), map),
+            Self::validate,
+        )
+    }
+
+    /// Create a cache that parses the specified bytes, in the format detected by
+    /// [`detect_format()`], instead of loading a file.
+    ///
+    /// The path of the cache is empty. See [`Self::from_bytes_with_path()`].
+    pub fn from_bytes(bytes: impl AsRef<[u8]> + Send + Sync + 'static) -> Result<Self> {
+        Self::from_bytes_with_path(bytes, PathBuf::default())
+    }
+
+    /// Create a cache that parses the specified bytes, in the format detected by
+    /// [`detect_format()`], instead of loading a file.
+    ///
+    /// `path` is returned by [`Self::path()`] and reported in errors, but it is not accessed.
+    pub fn from_bytes_with_path(
+        bytes: impl AsRef<[u8]> + Send + Sync + 'static,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self> {
+        Self::from_cache_bytes(path.into(), CacheBytes::from_owner(bytes))
+    }
+
+    fn from_cache_bytes(path: PathBuf, map: CacheBytes) -> Result<Self> {
+        let Some(detected) = detect_format(&map) else {
+            return Err(Error::MagicIsUnsupported { path });
+        };
+
+        match detected.format {
+            CacheFormat::LdSO1dot7 => {
+                ld_so_1dot7::Cache::from_cache_bytes(path, map).map(Self::LdSO1dot7)
+            }
+
+            CacheFormat::GLibCLdSOCache1dot1 => {
+                glibc_ld_so_cache_1dot1::Cache::from_cache_bytes(path, map)
+                    .map(Self::GLibCLdSOCache1dot1)
+            }
+
+            CacheFormat::LdELFSOHints => {
+                ld_elf_so_hints::Cache::from_cache_bytes(path, map).map(Self::LdELFSOHints)
+            }
+
+            CacheFormat::LdSOHints => {
+                ld_so_hints::Cache::from_cache_bytes(path, map).map(Self::LdSOHints)
+            }
+
+            CacheFormat::Custom => Err(Error::MagicIsUnsupported { path }),
+        }
+    }
+
+    /// Return the format of this cache.
+    #[must_use]
+    pub fn format(&self) -> CacheFormat {
+        match self {
+            Self::LdSO1dot7(_) => CacheFormat::LdSO1dot7,
+            Self::GLibCLdSOCache1dot1(_) => CacheFormat::GLibCLdSOCache1dot1,
+            Self::LdELFSOHints(_) => CacheFormat::LdELFSOHints,
+            Self::LdSOHints(_) => CacheFormat::LdSOHints,
+        }
+    }
+
+    /// Return the path of the cache file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::LdSO1dot7(cache) => cache.path(),
+            Self::GLibCLdSOCache1dot1(cache) => cache.path(),
+            Self::LdELFSOHints(cache) => cache.path(),
+            Self::LdSOHints(cache) => cache.path(),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self {
+            Self::LdSO1dot7(cache) => cache.validate(),
+            Self::GLibCLdSOCache1dot1(cache) => cache.validate(),
+            Self::LdELFSOHints(cache) => cache.validate(),
+            Self::LdSOHints(cache) => cache.validate(),
+        }
+    }
+
+    /// Return an iterator that returns the cache entries.
+    pub fn iter(&self) -> Result<impl FusedIterator<Item = Result<Entry<'_>>> + '_> {
+        AsRef::<dyn CacheProvider>::as_ref(self).entries_iter()
+    }
+
+    /// Return an iterator that returns the cache entries of the shared library named `name`.
+    ///
+    /// See [`Cache::lookup()`].
+    pub fn lookup(
+        &self,
+        name: impl AsRef<OsStr>,
+    ) -> Result<impl FusedIterator<Item = Result<Entry<'_>>> + '_> {
+        AsRef::<dyn CacheProvider>::as_ref(self).lookup(name.as_ref())
+    }
+}
+
+impl AsRef<dyn CacheProvider> for AnyCache {
     fn as_ref(&self) -> &(dyn CacheProvider + 'static) {
         match self {
             Self::LdSO1dot7(cache) => cache,
@@ -123,87 +467,168 @@ impl AsRef<dyn CacheProvider> for CacheImpl {
     }
 }
 
+/// Cache file configured to be loaded by a [`Cache`].
+#[derive(Debug, Clone)]
+struct CacheFile {
+    format: CacheFormat,
+    path: PathBuf,
+    /// Version of the file before it was loaded, or `None` if it could not be accessed.
+    version: Option<FileVersion>,
+    /// Cache loaded from the file, or `None` if loading failed.
+    cache: Option<Arc<AnyCache>>,
+}
+
 /// Reader of the dynamic loader shared libraries cache.
-#[derive(Debug)]
+///
+/// Cloning a cache is cheap, as the loaded cache files are shared between the clones.
+#[derive(Debug, Clone)]
 pub struct Cache {
-    caches: ArrayVec<CacheImpl, CACHE_IMPL_COUNT>,
+    files: Vec<CacheFile>,
+    /// Prefix of the paths of entries.
+    root: Option<PathBuf>,
+    /// Configuration used to load the caches.
+    builder: CacheBuilder,
+    /// Custom cache providers, searched after the loaded caches.
+    providers: Vec<Arc<dyn CacheProvider>>,
 }
 
 impl Cache {
     /// Load all dynamic loader caches supported and present on the system.
+    ///
+    /// See [`CacheBuilder`] in order to select the caches to load.
     pub fn load() -> Result<Self> {
         const_assert!(size_of::<u32>() <= size_of::<usize>());
 
-        let mut caches = ArrayVec::<CacheImpl, CACHE_IMPL_COUNT>::default();
-
-        if cfg!(target_os = "freebsd") {
-            Self::try_loading_ld_elf_so_hints(&mut caches)?;
-            Self::try_loading_ld_so_hints(&mut caches)?;
-            Self::try_loading_ld_so_1dot7(&mut caches)?;
-            Self::try_loading_glibc_ld_so_cache_1dot1(&mut caches)?;
-        } else if cfg!(any(target_os = "openbsd", target_os = "netbsd")) {
-            Self::try_loading_ld_so_hints(&mut caches)?;
-            Self::try_loading_ld_elf_so_hints(&mut caches)?;
-            Self::try_loading_ld_so_1dot7(&mut caches)?;
-            Self::try_loading_glibc_ld_so_cache_1dot1(&mut caches)?;
-        } else {
-            Self::try_loading_glibc_ld_so_cache_1dot1(&mut caches)?;
-            Self::try_loading_ld_elf_so_hints(&mut caches)?;
-            Self::try_loading_ld_so_hints(&mut caches)?;
-            Self::try_loading_ld_so_1dot7(&mut caches)?;
-        }
+        CacheBuilder::new().load()
+    }
 
-        Ok(Self { caches })
+    /// Load all dynamic loader caches supported and present on the system, and report the
+    /// outcome of loading each cache file.
+    ///
+    /// See [`CacheBuilder::load_with_report()`].
+    #[must_use]
+    pub fn load_with_report() -> (Self, Vec<LoadReport>) {
+        CacheBuilder::new().load_with_report()
     }
 
-    fn try_loading_glibc_ld_so_cache_1dot1(
-        caches: &mut ArrayVec<CacheImpl, CACHE_IMPL_COUNT>,
-    ) -> Result<()> {
-        if let Ok(cache) = glibc_ld_so_cache_1dot1::Cache::load_default() {
-            caches.push(CacheImpl::GLibCLdSOCache1dot1(cache));
-        }
-        Ok(())
+    /// Load all dynamic loader caches supported and present on the system whose root directory
+    /// is `root`, similarly to `chroot`.
+    ///
+    /// The paths of entries are relative to that system.
+    /// See [`CacheBuilder::root()`] and [`CacheBuilder::prefix_root()`] in order to prefix them
+    /// with `root`.
+    pub fn load_from_root(root: impl Into<PathBuf>) -> Result<Self> {
+        CacheBuilder::new().root(root).load()
     }
 
-    fn try_loading_ld_elf_so_hints(
-        caches: &mut ArrayVec<CacheImpl, CACHE_IMPL_COUNT>,
-    ) -> Result<()> {
-        for path in ld_elf_so_hints::CACHE_FILE_PATHS.iter().map(Path::new) {
-            if let Ok(cache) = ld_elf_so_hints::Cache::load(path) {
-                caches.push(CacheImpl::LdELFSOHints(cache));
-            }
-        }
-        Ok(())
+    /// Return `true` if any of the cache files changed since they were loaded,
+    /// *i.e.*, if their device, inode, size or modification time changed,
+    /// or if they were created or removed.
+    ///
+    /// See [`Cache::reload()`].
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        self.files
+            .iter()
+            .any(|file| FileVersion::of_path(&file.path) != file.version)
     }
 
-    fn try_loading_ld_so_hints(caches: &mut ArrayVec<CacheImpl, CACHE_IMPL_COUNT>) -> Result<()> {
-        if let Ok(cache) = ld_so_hints::Cache::load_default() {
-            caches.push(CacheImpl::LdSOHints(cache));
-        }
-        Ok(())
+    /// Load the caches again, with the same configuration.
+    ///
+    /// Only the cache files that changed since they were loaded are loaded again.
+    /// The caches of other files are shared with this cache, which remains valid,
+    /// so that entries borrowed from it can still be used while the new cache is published.
+    ///
+    /// See [`Cache::is_stale()`].
+    pub fn reload(&self) -> Result<Self> {
+        let mut cache = self.builder.reload(self)?;
+        cache.providers.clone_from(&self.providers);
+        Ok(cache)
     }
 
-    fn try_loading_ld_so_1dot7(caches: &mut ArrayVec<CacheImpl, CACHE_IMPL_COUNT>) -> Result<()> {
-        if let Ok(cache) = ld_so_1dot7::Cache::load_default() {
-            caches.push(CacheImpl::LdSO1dot7(cache));
-        }
-        Ok(())
+    /// Register a custom cache provider, whose entries are aggregated with the entries of the
+    /// loaded caches.
+    ///
+    /// Providers are searched after the loaded caches, in the order of their registration.
+    /// The paths of their entries are prefixed similarly to the entries of the loaded caches
+    /// (see [`CacheBuilder::prefix_root()`]).
+    #[must_use]
+    pub fn with_provider(mut self, provider: Box<dyn CacheProvider>) -> Self {
+        self.providers.push(Arc::from(provider));
+        self
+    }
+
+    fn caches(&self) -> impl Iterator<Item = &AnyCache> {
+        self.files.iter().filter_map(|file| file.cache.as_deref())
+    }
+
+    /// Return the loaded caches, followed by the custom cache providers.
+    fn providers(&self) -> impl Iterator<Item = &(dyn CacheProvider + 'static)> + '_ {
+        self.caches()
+            .map(AsRef::as_ref)
+            .chain(self.providers.iter().map(Arc::as_ref))
     }
 
     /// Returns an iterator that returns the cache entries.
     ///
-    /// The entries are aggregated from all dynamic loader caches that have been previously loaded.
+    /// The entries are aggregated from all dynamic loader caches that have been previously loaded,
+    /// and from custom cache providers.
     pub fn iter(&self) -> Result<impl FusedIterator<Item = Result<Entry<'_>>> + '_> {
         Ok(self
-            .caches
-            .iter()
-            .map(AsRef::as_ref)
+            .providers()
             .map(CacheProvider::entries_iter)
-            .collect::<Result<ArrayVec<_, CACHE_IMPL_COUNT>>>()?
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .map(|entry| self.prefix_root(entry))
+            .fuse())
+    }
+
+    /// Return an iterator that returns the cache entries of the shared library named `name`.
+    ///
+    /// The entries are aggregated from all dynamic loader caches that have been previously loaded,
+    /// and from custom cache providers.
+    /// Each cache is searched through its own index when it has one, *e.g.*, the sorted entries
+    /// of `glibc-ld.so.cache1.1` caches, the hash table of `ld.so.hints` caches,
+    /// or the directories of `ld-elf.so.hints` caches.
+    /// Other caches are scanned entirely.
+    ///
+    /// Entries have the file name `name`, except for entries of `ld.so.hints` caches:
+    /// similarly to the OpenBSD and NetBSD dynamic loaders, these have the major version of `name`,
+    /// and the highest minor version that is not lower than the one of `name`,
+    /// *e.g.*, `libc.so.97.2` for `libc.so.97.1` or `libc.so.97`.
+    pub fn lookup(
+        &self,
+        name: impl AsRef<OsStr>,
+    ) -> Result<impl FusedIterator<Item = Result<Entry<'_>>> + '_> {
+        let name = name.as_ref();
+        Ok(self
+            .providers()
+            .map(|cache| cache.lookup(name))
+            .collect::<Result<Vec<_>>>()?
             .into_iter()
             .flatten()
+            .map(|entry| self.prefix_root(entry))
             .fuse())
     }
+
+    fn prefix_root<'cache>(&self, entry: Result<Entry<'cache>>) -> Result<Entry<'cache>> {
+        match &self.root {
+            Some(root) => entry.map(|entry| Entry {
+                full_path: Cow::Owned(resolve_in_root(root, &entry.full_path)),
+                ..entry
+            }),
+            None => entry,
+        }
+    }
+
+    /// Return the first cache entry of the shared library named `name`,
+    /// or `None` if no entry was found.
+    ///
+    /// See [`Cache::lookup()`].
+    pub fn lookup_first(&self, name: impl AsRef<OsStr>) -> Result<Option<Entry<'_>>> {
+        self.lookup(name)?.next().transpose()
+    }
 }
 
 #[cfg(test)]
@@ -223,3 +648,191 @@ fn test1() {
     let cache = Cache::load().unwrap();
     print_cache(&cache);
 }
+
+#[test]
+fn lookup() {
+    let cache = Cache::load().unwrap();
+
+    for entry in cache.iter().unwrap().filter_map(Result::ok).take(64) {
+        let found: Vec<_> = cache
+            .lookup(&entry.file_name)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert!(found.iter().any(|e| e.full_path == entry.full_path));
+
+        let first = cache.lookup_first(&entry.file_name).unwrap().unwrap();
+        assert_eq!(first.full_path, found[0].full_path);
+    }
+
+    assert!(cache.lookup_first("libmissing.so.1").unwrap().is_none());
+}
+
+#[test]
+fn lookup_ld_so_hints() {
+    let cache = CacheBuilder::new()
+        .paths(CacheFormat::GLibCLdSOCache1dot1, Vec::<PathBuf>::default())
+        .paths(CacheFormat::LdSO1dot7, Vec::<PathBuf>::default())
+        .paths(CacheFormat::LdELFSOHints, Vec::<PathBuf>::default())
+        .paths(
+            CacheFormat::LdSOHints,
+            ["tests/ld.so.hints/ld.so.hints-be32"],
+        )
+        .load()
+        .unwrap();
+
+    // Entries have the highest minor version of the requested major version.
+    for name in ["libc.so.96", "libc.so.96.1", "libc.so.96.0"] {
+        let entry = cache.lookup_first(name).unwrap().unwrap();
+        assert_eq!(entry.file_name, OsStr::new("libc.so.96.1"));
+        assert_eq!(entry.full_path, Path::new("/usr/lib/libc.so.96.1"));
+    }
+    assert!(cache.lookup_first("libc.so.96.2").unwrap().is_none());
+
+    // File names of entries are the same as returned by `Cache::iter()`.
+    for entry in cache.iter().unwrap().map(Result::unwrap) {
+        let found = cache.lookup_first(&entry.file_name).unwrap().unwrap();
+        assert_eq!(found.file_name, entry.file_name);
+        assert_eq!(found.full_path, entry.full_path);
+    }
+}
+
+#[test]
+fn custom_provider() {
+    /// Shared libraries vendored with an application.
+    #[derive(Debug)]
+    struct Vendored(Vec<PathBuf>);
+
+    impl CacheProvider for Vendored {
+        fn entries_iter<'cache>(
+            &'cache self,
+        ) -> Result<Box<dyn FusedIterator<Item = Result<Entry<'cache>>> + 'cache>> {
+            let iter = self.0.iter().map(|path| {
+                Ok(Entry::new(
+                    path.file_name().unwrap(),
+                    path.as_path(),
+                    CacheFormat::Custom,
+                    Path::new("vendored.json"),
+                ))
+            });
+            Ok(Box::new(iter))
+        }
+    }
+
+    let vendored = Vendored(vec![
+        PathBuf::from("/opt/app/lib/libvendored.so.1"),
+        PathBuf::from("/opt/app/lib/libc.so.6"),
+    ]);
+    let cache = CacheBuilder::new()
+        .paths(
+            CacheFormat::GLibCLdSOCache1dot1,
+            ["tests/glibc-ld.so.cache1.1/ld.so.cache"],
+        )
+        .load()
+        .unwrap()
+        .with_provider(Box::new(vendored));
+
+    let entry = cache.lookup_first("libvendored.so.1").unwrap().unwrap();
+    assert_eq!(entry.full_path, Path::new("/opt/app/lib/libvendored.so.1"));
+    assert_eq!(entry.cache_format, CacheFormat::Custom);
+    assert_eq!(entry.cache_path, Path::new("vendored.json"));
+
+    // Custom providers are searched after the loaded caches.
+    let entries: Vec<_> = cache
+        .lookup("libc.so.6")
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert!(entries.len() > 1);
+    assert_ne!(entries[0].cache_format, CacheFormat::Custom);
+    assert_eq!(entries.last().unwrap().cache_format, CacheFormat::Custom);
+
+    // Custom providers are kept when the caches are reloaded.
+    let cache = cache.reload().unwrap();
+    assert!(cache.lookup_first("libvendored.so.1").unwrap().is_some());
+    assert_eq!(
+        cache.iter().unwrap().count(),
+        cache
+            .caches()
+            .map(|c| c.iter().unwrap().count())
+            .sum::<usize>()
+            + 2
+    );
+}
+
+#[test]
+fn owned_entries() {
+    #[cfg(feature = "serde")]
+    static_assertions::assert_impl_all!(
+        OwnedEntry: serde::Serialize,
+        serde::de::DeserializeOwned,
+        Send
+    );
+
+    let cache = CacheBuilder::new()
+        .paths(
+            CacheFormat::GLibCLdSOCache1dot1,
+            ["tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps"],
+        )
+        .load()
+        .unwrap();
+    let entries: Vec<_> = cache.iter().unwrap().map(Result::unwrap).collect();
+    let mut owned: Vec<OwnedEntry> = entries.iter().cloned().map(Entry::into_owned).collect();
+    assert_eq!(owned, entries);
+
+    // Equal entries have equal hashes, whether they borrow from the cache or not.
+    use std::hash::{Hash, Hasher};
+    let hash = |entry: &Entry<'_>| {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        entry.hash(&mut hasher);
+        hasher.finish()
+    };
+    assert!(matches!(entries[0].file_name, Cow::Borrowed(_)));
+    assert!(matches!(owned[0].file_name, Cow::Owned(_)));
+    assert!(entries
+        .iter()
+        .zip(&owned)
+        .all(|(entry, owned)| hash(entry) == hash(owned)));
+    drop(entries);
+    drop(cache);
+
+    // Owned entries outlive the cache, and can be sent to other threads.
+    let sorted = std::thread::spawn(move || {
+        owned.sort();
+        owned
+    })
+    .join()
+    .unwrap();
+    assert!(sorted.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!(sorted.iter().any(|entry| matches!(
+        &entry.metadata,
+        EntryMetadata::GLibCLdSOCache1dot1(metadata) if metadata.hwcaps_subdirectory.is_some()
+    )));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_entries() {
+    let caches = [
+        "tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps",
+        "tests/ld.so.hints/ld.so.hints",
+    ]
+    .map(|path| AnyCache::load(path).unwrap());
+
+    let entries: Vec<_> = caches
+        .iter()
+        .flat_map(|cache| cache.iter().unwrap())
+        .map(Result::unwrap)
+        .collect();
+    assert!(entries.iter().any(|entry| matches!(
+        &entry.metadata,
+        EntryMetadata::GLibCLdSOCache1dot1(metadata) if metadata.hwcaps_subdirectory.is_some()
+    )));
+    assert!(entries
+        .iter()
+        .any(|entry| matches!(entry.metadata, EntryMetadata::LdSOHints(_))));
+
+    let json = serde_json::to_string(&entries).unwrap();
+    let deserialized: Vec<OwnedEntry> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, entries);
+}
@@ -0,0 +1,397 @@
+// Copyright 2024 Koutheir Attouchi.
+// See the "LICENSE.txt" file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license. This file may not be copied, modified,
+// or distributed except according to those terms.
+
+//! Configuration of the shared libraries mappings of the FreeBSD dynamic loader.
+//!
+//! The FreeBSD dynamic loader replaces the names of some shared libraries by other names or paths,
+//! as configured in `/etc/libmap.conf`, before it searches for those libraries.
+//! Mappings can apply to all executables, or only to the executables selected by
+//! a `[selector]` line.
+
+#[cfg(test)]
+mod tests;
+
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+use crate::utils::{elf_class_of_file, path_from_bytes, resolve_in_root};
+use crate::{ld_elf_so_hints, ElfClass, Error, Result};
+
+static CONFIG_FILE_PATH: &str = "/etc/libmap.conf";
+static CONFIG_FILE_PATH_32: &str = "/etc/libmap32.conf";
+
+/// Name of the section that applies to all executables.
+const DEFAULT_SELECTOR: &[u8] = b"$DEFAULT$";
+
+/// Selector of the executables to which a section of mappings applies.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Selector {
+    /// All executables, *i.e.*, mappings that precede any selector, or follow `[$DEFAULT$]`.
+    Default,
+    /// The executable at the exact path, *e.g.*, `[/usr/local/bin/mplayer]`.
+    Exact(PathBuf),
+    /// All executables under the directory, *e.g.*, `[/usr/local/bin/]`.
+    Directory(PathBuf),
+    /// All executables with the file name, *e.g.*, `[mplayer]`.
+    BaseName(OsString),
+}
+
+impl Selector {
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes == DEFAULT_SELECTOR {
+            return Ok(Self::Default);
+        }
+
+        let path = path_from_bytes(bytes)?.into_owned();
+        Ok(if bytes.ends_with(b"/") {
+            Self::Directory(path)
+        } else if bytes.contains(&b'/') {
+            Self::Exact(path)
+        } else {
+            Self::BaseName(path.into_os_string())
+        })
+    }
+
+    /// Return `true` if the executable at `program` is selected.
+    #[must_use]
+    pub fn matches(&self, program: &Path) -> bool {
+        let program = program.as_os_str().as_encoded_bytes();
+
+        match self {
+            Self::Default => true,
+            Self::Exact(path) => program == path.as_os_str().as_encoded_bytes(),
+            Self::Directory(path) => program.starts_with(path.as_os_str().as_encoded_bytes()),
+            Self::BaseName(name) => {
+                let base_name = program.rsplit(|&b| b == b'/').next().unwrap_or(program);
+                base_name == name.as_encoded_bytes()
+            }
+        }
+    }
+}
+
+/// Mapping of a shared library name to another name or path.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Mapping {
+    /// Name of the shared library, as requested by an executable or a shared library.
+    pub from: OsString,
+    /// Name or path of the shared library to load instead.
+    pub to: PathBuf,
+}
+
+/// Mappings that apply to the executables selected by a selector.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Section {
+    /// Selector of the executables to which the mappings apply.
+    pub selector: Selector,
+    /// Mappings, in their order of appearance.
+    pub mappings: Vec<Mapping>,
+}
+
+/// Shared libraries mappings of the FreeBSD dynamic loader.
+///
+/// This loads a configuration file (*e.g.*, `/etc/libmap.conf`), and the files it includes
+/// through `include` and `includedir` directives.
+/// Similarly to the dynamic loader, malformed lines are ignored, as well as included files
+/// that cannot be read, and each file is parsed at most once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    files: Vec<PathBuf>,
+    sections: Vec<Section>,
+    root: Option<PathBuf>,
+}
+
+impl Config {
+    /// Create a configuration that loads the file `/etc/libmap.conf`.
+    pub fn load_default() -> Result<Self> {
+        Self::load_for_abi(ElfClass::NATIVE)
+    }
+
+    /// Create a configuration that loads the configuration file of the dynamic loader
+    /// of the specified ABI.
+    ///
+    /// This loads `/etc/libmap.conf` for the native ABI, and `/etc/libmap32.conf`
+    /// for the 32-bits compatibility ABI of 64-bits systems.
+    pub fn load_for_abi(abi: ElfClass) -> Result<Self> {
+        Self::load_with_root(None, Self::path_for_abi(abi)?)
+    }
+
+    /// Create a configuration that loads the configuration file of the dynamic loader
+    /// of the specified ABI, on the system whose root directory is `root`, similarly to `chroot`.
+    ///
+    /// The paths of the configuration file and of the files it includes, and the symbolic links
+    /// they traverse, are resolved under `root`.
+    /// The paths returned by [`Self::files()`] remain relative to that system.
+    pub fn load_from_root(root: impl Into<PathBuf>, abi: ElfClass) -> Result<Self> {
+        Self::load_with_root(Some(root.into()), Self::path_for_abi(abi)?)
+    }
+
+    /// Create a configuration that loads the specified configuration file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::load_with_root(None, path.as_ref())
+    }
+
+    fn load_with_root(root: Option<PathBuf>, path: &Path) -> Result<Self> {
+        let mut config = Self {
+            root,
+            ..Self::default()
+        };
+
+        let bytes = std::fs::read(config.host_path(path)).map_err(|source| Error::Read {
+            path: path.into(),
+            source,
+        })?;
+
+        config.files.push(path.into());
+        config.parse(&bytes)?;
+        Ok(config)
+    }
+
+    fn path_for_abi(abi: ElfClass) -> Result<&'static Path> {
+        let path = match (abi, ElfClass::NATIVE) {
+            (ElfClass::Elf32, ElfClass::Elf32) | (ElfClass::Elf64, ElfClass::Elf64) => {
+                CONFIG_FILE_PATH
+            }
+            (ElfClass::Elf32, ElfClass::Elf64) => CONFIG_FILE_PATH_32,
+            (ElfClass::Elf64, ElfClass::Elf32) => return Err(Error::AbiIsUnsupported { abi }),
+        };
+        Ok(Path::new(path))
+    }
+
+    /// Return the root directory of the system this configuration belongs to, if it was set.
+    #[must_use]
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Return the path on the host of the specified path of the system this configuration
+    /// belongs to.
+    fn host_path<'path>(&self, path: &'path Path) -> Cow<'path, Path> {
+        match &self.root {
+            Some(root) => Cow::Owned(resolve_in_root(root, path)),
+            None => Cow::Borrowed(path),
+        }
+    }
+
+    /// Return the paths of the parsed configuration files, in their order of parsing.
+    #[must_use]
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Return the sections of mappings, in their order of first appearance.
+    ///
+    /// Mappings of sections with identical selectors are merged into the first such section.
+    #[must_use]
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Return the name or path that replaces the shared library `name`,
+    /// when it is needed by the executable at `program`.
+    ///
+    /// Similarly to the dynamic loader, the last section that selects `program` is searched
+    /// first, then the section that applies to all executables.
+    /// In each section, the last mapping of `name` wins.
+    #[must_use]
+    pub fn find(&self, program: Option<&Path>, name: impl AsRef<OsStr>) -> Option<&Path> {
+        let name = name.as_ref();
+
+        program
+            .and_then(|program| {
+                self.sections.iter().rev().find(|section| {
+                    section.selector != Selector::Default && section.selector.matches(program)
+                })
+            })
+            .and_then(|section| Self::find_in_section(section, name))
+            .or_else(|| {
+                self.sections
+                    .iter()
+                    .find(|section| section.selector == Selector::Default)
+                    .and_then(|section| Self::find_in_section(section, name))
+            })
+    }
+
+    fn find_in_section<'config>(section: &'config Section, name: &OsStr) -> Option<&'config Path> {
+        section
+            .mappings
+            .iter()
+            .rev()
+            .find(|mapping| mapping.from == name)
+            .map(|mapping| mapping.to.as_path())
+    }
+
+    fn parse(&mut self, bytes: &[u8]) -> Result<()> {
+        // Every file starts with mappings that apply to all executables.
+        let mut selector = Selector::Default;
+
+        for line in bytes.split(|&b| b == b'\n') {
+            match parse_line(line) {
+                Line::Empty => {}
+
+                Line::Selector(bytes) => selector = Selector::from_bytes(bytes)?,
+
+                Line::Mapping(b"include", path) => self.parse_file(&path_from_bytes(path)?),
+
+                Line::Mapping(b"includedir", path) => self.parse_dir(&path_from_bytes(path)?),
+
+                Line::Mapping(from, to) => {
+                    let mapping = Mapping {
+                        from: path_from_bytes(from)?.into_owned().into_os_string(),
+                        to: path_from_bytes(to)?.into_owned(),
+                    };
+                    self.add_mapping(&selector, mapping);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_file(&mut self, path: &Path) {
+        if self.files.iter().any(|parsed| parsed == path) {
+            return;
+        }
+
+        let Ok(bytes) = std::fs::read(self.host_path(path)) else {
+            return;
+        };
+
+        self.files.push(path.into());
+        // Errors are ignored, similarly to malformed lines.
+        let _ignored = self.parse(&bytes);
+    }
+
+    fn parse_dir(&mut self, path: &Path) {
+        let Ok(entries) = read_dir(self.host_path(path)) else {
+            return;
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| path.join(entry.file_name()))
+            .filter(|path| path.extension() == Some(OsStr::new("conf")))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            self.parse_file(&path);
+        }
+    }
+
+    fn add_mapping(&mut self, selector: &Selector, mapping: Mapping) {
+        if let Some(section) = self.sections.iter_mut().find(|s| s.selector == *selector) {
+            section.mappings.push(mapping);
+        } else {
+            self.sections.push(Section {
+                selector: selector.clone(),
+                mappings: vec![mapping],
+            });
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Line<'bytes> {
+    Empty,
+    Selector(&'bytes [u8]),
+    Mapping(&'bytes [u8], &'bytes [u8]),
+}
+
+/// Parse a line of a configuration file, similarly to the dynamic loader.
+///
+/// Malformed lines are parsed as empty lines.
+fn parse_line(line: &[u8]) -> Line<'_> {
+    // Comments extend to the end of the line.
+    let line = line
+        .split(|&b| b == b'#' || b == b'\r')
+        .next()
+        .unwrap_or_default();
+
+    if let Some(selector) = line.trim_ascii_start().strip_prefix(b"[") {
+        let Some((selector, rest)) = split_once(selector, b']') else {
+            return Line::Empty;
+        };
+
+        let selector = selector.trim_ascii();
+        return if selector.is_empty()
+            || selector.iter().any(u8::is_ascii_whitespace)
+            || !rest.trim_ascii().is_empty()
+        {
+            Line::Empty
+        } else {
+            Line::Selector(selector)
+        };
+    }
+
+    let mut words = line
+        .split(u8::is_ascii_whitespace)
+        .filter(|w| !w.is_empty());
+    match (words.next(), words.next(), words.next()) {
+        (Some(from), Some(to), None) => Line::Mapping(from, to),
+        _ => Line::Empty,
+    }
+}
+
+fn split_once(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let index = bytes.iter().position(|&b| b == separator)?;
+    Some((&bytes[..index], &bytes[index.saturating_add(1)..]))
+}
+
+/// Resolver of shared libraries that applies the mappings of `libmap.conf`
+/// before searching the directories of an `ld-elf.so.hints` cache.
+#[derive(Debug, Clone, Copy)]
+pub struct Resolver<'resolver> {
+    config: &'resolver Config,
+    cache: &'resolver ld_elf_so_hints::Cache,
+}
+
+impl<'resolver> Resolver<'resolver> {
+    /// Create a resolver that applies the mappings of `config`, then searches the directories
+    /// of `cache`.
+    #[must_use]
+    pub fn new(config: &'resolver Config, cache: &'resolver ld_elf_so_hints::Cache) -> Self {
+        Self { config, cache }
+    }
+
+    /// Return the full path of the shared library `name`, when it is needed by the executable
+    /// at `program`.
+    ///
+    /// If `name` is mapped to a path, then that path is returned.
+    /// Otherwise, the directories of the cache are searched, in order, for a file named `name`,
+    /// or named after the mapping of `name`.
+    /// In both cases, only ELF files of the ABI of the cache are returned.
+    /// Paths from other sources (*e.g.*, `LD_LIBRARY_PATH`, `DT_RUNPATH`) are not searched.
+    ///
+    /// If the cache has a root directory (see [`ld_elf_so_hints::Cache::with_root()`]), then
+    /// the directories are searched under that root, and the returned path remains relative
+    /// to the system the cache belongs to.
+    pub fn resolve(
+        &self,
+        program: Option<&Path>,
+        name: impl AsRef<OsStr>,
+    ) -> Result<Option<PathBuf>> {
+        let name = name.as_ref();
+        let name = self.config.find(program, name).unwrap_or(Path::new(name));
+
+        let abi = self.cache.abi();
+        let is_library = |path: &Path| elf_class_of_file(&self.cache.host_path(path)) == Some(abi);
+
+        if name.as_os_str().as_encoded_bytes().contains(&b'/') {
+            return Ok(is_library(name).then(|| name.into()));
+        }
+
+        for directory in self.cache.directories()? {
+            let full_path = directory?.join(name);
+            if is_library(&full_path) {
+                return Ok(Some(full_path));
+            }
+        }
+        Ok(None)
+    }
+}
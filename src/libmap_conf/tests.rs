@@ -0,0 +1,249 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use super::{parse_line, Config, Line, Mapping, Resolver, Section, Selector};
+use crate::{ld_elf_so_hints, ElfClass};
+
+fn mapping(from: &str, to: &str) -> Mapping {
+    Mapping {
+        from: from.into(),
+        to: to.into(),
+    }
+}
+
+#[test]
+fn load() {
+    let config = Config::load("tests/libmap.conf/libmap.conf").unwrap();
+
+    assert_eq!(
+        config.files(),
+        [
+            "tests/libmap.conf/libmap.conf",
+            "tests/libmap.conf/libmap.d/10-drm.conf",
+            "tests/libmap.conf/libmap.d/20-ssl.conf",
+        ]
+        .map(PathBuf::from)
+    );
+
+    assert_eq!(
+        config.sections(),
+        [
+            Section {
+                selector: Selector::Exact("/usr/local/bin/mplayer".into()),
+                mappings: vec![
+                    mapping("libdrm.so.2", "libdrm.so.1"),
+                    mapping("libpthread.so.2", "libc_r.so.6"),
+                ],
+            },
+            Section {
+                selector: Selector::Default,
+                mappings: vec![
+                    mapping("libssl.so.8", "libssl.so.111"),
+                    mapping("libpthread.so.2", "libthr.so.3"),
+                    mapping("libGL.so.1", "/usr/local/lib/nvidia/libGL.so.1"),
+                    mapping("libcrypt.so.5", "libcrypt.so.4"),
+                ],
+            },
+            Section {
+                selector: Selector::Directory("/usr/local/bin/".into()),
+                mappings: vec![mapping("libiconv.so.3", "libiconv.so.2")],
+            },
+            Section {
+                selector: Selector::BaseName(OsString::from("firefox")),
+                mappings: vec![
+                    mapping("libz.so.6", "/usr/local/lib/libz.so.1"),
+                    mapping("libm.so.5", "libm.so.4"),
+                ],
+            },
+        ]
+    );
+}
+
+#[test]
+fn load_missing() {
+    Config::load("tests/libmap.conf/missing.conf").unwrap_err();
+}
+
+#[test]
+fn find() {
+    let config = Config::load("tests/libmap.conf/libmap.conf").unwrap();
+    let mplayer = Some(Path::new("/usr/local/bin/mplayer"));
+    let vlc = Some(Path::new("/usr/local/bin/vlc"));
+    let firefox = Some(Path::new("/usr/bin/firefox"));
+
+    for (program, name, expected) in [
+        (None, "libpthread.so.2", Some("libthr.so.3")),
+        (None, "libm.so.5", None),
+        // The last selected section hides the sections that precede it, similarly to the
+        // dynamic loader, so the section `[/usr/local/bin/]` hides `[/usr/local/bin/mplayer]`.
+        (mplayer, "libpthread.so.2", Some("libthr.so.3")),
+        (mplayer, "libcrypt.so.5", Some("libcrypt.so.4")),
+        (mplayer, "libiconv.so.3", Some("libiconv.so.2")),
+        (mplayer, "libdrm.so.2", None),
+        (vlc, "libiconv.so.3", Some("libiconv.so.2")),
+        (vlc, "libpthread.so.2", Some("libthr.so.3")),
+        (firefox, "libz.so.6", Some("/usr/local/lib/libz.so.1")),
+        (firefox, "libm.so.5", Some("libm.so.4")),
+        (firefox, "libz.so.5", None),
+    ] {
+        assert_eq!(config.find(program, name), expected.map(Path::new));
+    }
+}
+
+#[test]
+fn find_duplicates() {
+    let mut config = Config::default();
+    config
+        .parse(
+            b"libc.so.6 libc.so.7\n\
+              libc.so.6 libc.so.8\n\
+              [ls]\n\
+              libm.so.5 libm.so.3\n\
+              [/bin/]\n\
+              libm.so.5 libm.so.4\n\
+              [ls]\n\
+              libm.so.5 libm.so.2\n",
+        )
+        .unwrap();
+
+    // The last mapping wins, and the last section that selects a program is searched first.
+    assert_eq!(config.find(None, "libc.so.6"), Some(Path::new("libc.so.8")));
+    assert_eq!(
+        config.find(Some(Path::new("/bin/ls")), "libm.so.5"),
+        Some(Path::new("libm.so.4"))
+    );
+    assert_eq!(
+        config.find(Some(Path::new("/usr/bin/ls")), "libm.so.5"),
+        Some(Path::new("libm.so.2"))
+    );
+}
+
+#[test]
+fn selector_matches() {
+    let exact = Selector::Exact("/usr/bin/ls".into());
+    assert!(exact.matches(Path::new("/usr/bin/ls")));
+    assert!(!exact.matches(Path::new("/bin/ls")));
+
+    let directory = Selector::Directory("/usr/bin/".into());
+    assert!(directory.matches(Path::new("/usr/bin/ls")));
+    assert!(!directory.matches(Path::new("/usr/local/bin/ls")));
+
+    let base_name = Selector::BaseName("ls".into());
+    assert!(base_name.matches(Path::new("/usr/bin/ls")));
+    assert!(base_name.matches(Path::new("ls")));
+    assert!(!base_name.matches(Path::new("/usr/bin/lsof")));
+
+    assert!(Selector::Default.matches(Path::new("/usr/bin/ls")));
+}
+
+#[test]
+fn parse_lines() {
+    for (line, expected) in [
+        (&b""[..], Line::Empty),
+        (b"  # comment", Line::Empty),
+        (b"[/usr/bin/]", Line::Selector(b"/usr/bin/")),
+        (b" [ ls ]  # comment", Line::Selector(b"ls")),
+        (b"[]", Line::Empty),
+        (b"[ls", Line::Empty),
+        (b"[ls] garbage", Line::Empty),
+        (b"[l s]", Line::Empty),
+        (
+            b"libc.so.7\t/lib/libc.so.7",
+            Line::Mapping(b"libc.so.7", b"/lib/libc.so.7"),
+        ),
+        (
+            b"libc.so.7 libc.so.6#comment",
+            Line::Mapping(b"libc.so.7", b"libc.so.6"),
+        ),
+        (
+            b"libc.so.7 libc.so.6\r",
+            Line::Mapping(b"libc.so.7", b"libc.so.6"),
+        ),
+        (b"libc.so.7", Line::Empty),
+        (b"libc.so.7 libc.so.6 libc.so.5", Line::Empty),
+    ] {
+        assert_eq!(parse_line(line), expected);
+    }
+}
+
+#[test]
+fn resolve() {
+    let config = Config::load("tests/libmap.conf/libmap.conf").unwrap();
+    let cache = ld_elf_so_hints::Cache::load("tests/libmap.conf/ld-elf.so.hints").unwrap();
+    let resolver = Resolver::new(&config, &cache);
+    let mplayer = Some(Path::new("/usr/local/bin/mplayer"));
+
+    // `lib/libthr.so.3` is a 32-bits library, so it is skipped for 64-bits programs.
+    for (program, name, expected) in [
+        (
+            None,
+            "libpthread.so.2",
+            Some("tests/libmap.conf/lib/compat/libthr.so.3"),
+        ),
+        (
+            None,
+            "libthr.so.3",
+            Some("tests/libmap.conf/lib/compat/libthr.so.3"),
+        ),
+        (
+            mplayer,
+            "libpthread.so.2",
+            Some("tests/libmap.conf/lib/compat/libthr.so.3"),
+        ),
+        (None, "libGL.so.1", None),
+        (
+            None,
+            "libc_r.so.6",
+            Some("tests/libmap.conf/lib/compat/libc_r.so.6"),
+        ),
+        (None, "libcrypt.so.5", None),
+    ] {
+        let full_path = resolver.resolve(program, name).unwrap();
+        assert_eq!(full_path, expected.map(PathBuf::from));
+    }
+}
+
+#[test]
+fn resolve_mapped_path_abi() {
+    let mut config = Config::default();
+    config
+        .parse(
+            b"libc.so.7 tests/ld-elf.so.hints/lib/libc.so.7\n\
+              libc32.so.7 tests/ld-elf.so.hints/lib/libc32.so.7\n",
+        )
+        .unwrap();
+    let cache = ld_elf_so_hints::Cache::load("tests/libmap.conf/ld-elf.so.hints").unwrap();
+    let resolver = Resolver::new(&config, &cache);
+
+    assert_eq!(
+        resolver.resolve(None, "libc.so.7").unwrap(),
+        Some(PathBuf::from("tests/ld-elf.so.hints/lib/libc.so.7"))
+    );
+    assert_eq!(resolver.resolve(None, "libc32.so.7").unwrap(), None);
+}
+
+#[test]
+fn root() {
+    let config = Config::load_from_root("tests/root", ElfClass::NATIVE).unwrap();
+    assert_eq!(config.root(), Some(Path::new("tests/root")));
+    assert_eq!(
+        config.files(),
+        ["/etc/libmap.conf", "/etc/libmap.d/10-thr.conf"].map(PathBuf::from)
+    );
+
+    let cache = ld_elf_so_hints::Cache::load("tests/root/var/run/ld-elf.so.hints")
+        .unwrap()
+        .with_root("tests/root");
+    let resolver = Resolver::new(&config, &cache);
+
+    // The directory `/lib` of the cache is a symbolic link to `/fixture/lib`, under the root.
+    for (name, expected) in [
+        ("libc.so.7", Some("/lib/libc.so.7")),
+        ("libc.so.6", Some("/lib/libc.so.7")),
+        ("libthr.so.3", Some("/fixture/lib/libc.so.7")),
+        ("libm.so.5", None),
+    ] {
+        let full_path = resolver.resolve(None, name).unwrap();
+        assert_eq!(full_path, expected.map(PathBuf::from));
+    }
+}
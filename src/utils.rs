@@ -4,21 +4,25 @@
 // Licensed under the MIT license. This file may not be copied, modified,
 // or distributed except according to those terms.
 
+#[cfg(test)]
+mod tests;
+
 use std::borrow::Cow;
-use std::ffi::CStr;
-#[cfg(unix)]
-use std::ffi::OsStr;
 #[cfg(not(unix))]
 use std::ffi::OsString;
-use std::fs::File;
-use std::path::Path;
-#[cfg(not(unix))]
-use std::path::PathBuf;
+use std::ffi::{CStr, OsStr};
+use std::fmt;
+use std::fs::read_link;
+use std::fs::{File, Metadata};
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
 
 use memmap2::{Mmap, MmapOptions};
 
 use crate::errors::Error;
-use crate::Result;
+use crate::{CacheFormat, ElfClass, LoadStrategy, Result};
 
 #[cfg(unix)]
 pub(crate) fn os_str_from_cstr(cstr: &CStr) -> Result<&OsStr> {
@@ -35,6 +39,16 @@ pub(crate) fn os_string_from_cstr(cstr: &CStr) -> Result<OsString> {
     Ok(OsString::from_wide(&wstr))
 }
 
+#[cfg(unix)]
+pub(crate) fn cow_os_str_from_cstr(cstr: &CStr) -> Result<Cow<'_, OsStr>> {
+    os_str_from_cstr(cstr).map(Cow::Borrowed)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn cow_os_str_from_cstr(cstr: &CStr) -> Result<Cow<'_, OsStr>> {
+    os_string_from_cstr(cstr).map(Cow::Owned)
+}
+
 #[cfg(unix)]
 pub(crate) fn path_from_cstr(cstr: &CStr) -> Result<&Path> {
     os_str_from_cstr(cstr).map(Path::new)
@@ -46,14 +60,14 @@ pub(crate) fn path_buf_from_cstr(cstr: &CStr) -> Result<PathBuf> {
 }
 
 #[cfg(unix)]
-pub(crate) fn path_from_bytes(bytes: &[u8]) -> Result<Cow<Path>> {
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> Result<Cow<'_, Path>> {
     use std::os::unix::ffi::OsStrExt;
 
     Ok(Cow::Borrowed(Path::new(OsStr::from_bytes(bytes))))
 }
 
 #[cfg(windows)]
-pub(crate) fn path_from_bytes(bytes: &[u8]) -> Result<Cow<Path>> {
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> Result<Cow<'_, Path>> {
     use std::os::windows::ffi::OsStringExt;
 
     let wstr: Vec<_> = std::str::from_utf8(bytes)?.encode_utf16().collect();
@@ -64,6 +78,9 @@ pub(crate) fn path_from_bytes(bytes: &[u8]) -> Result<Cow<Path>> {
 pub(crate) fn cstr_entry_to_crate_entry<'cache>(
     key: &'cache CStr,
     value: &'cache CStr,
+    metadata: crate::EntryMetadata<'cache>,
+    cache_format: CacheFormat,
+    cache_path: &'cache Path,
 ) -> Result<crate::Entry<'cache>> {
     let file_name = os_str_from_cstr(key).map(Cow::Borrowed)?;
     let full_path = path_from_cstr(value).map(Cow::Borrowed)?;
@@ -71,6 +88,9 @@ pub(crate) fn cstr_entry_to_crate_entry<'cache>(
     Ok(crate::Entry {
         file_name,
         full_path,
+        metadata,
+        cache_format,
+        cache_path: Cow::Borrowed(cache_path),
     })
 }
 
@@ -78,6 +98,9 @@ pub(crate) fn cstr_entry_to_crate_entry<'cache>(
 pub(crate) fn cstr_entry_to_crate_entry<'cache>(
     key: &'cache CStr,
     value: &'cache CStr,
+    metadata: crate::EntryMetadata<'cache>,
+    cache_format: CacheFormat,
+    cache_path: &'cache Path,
 ) -> Result<crate::Entry<'cache>> {
     let file_name = os_string_from_cstr(key).map(Cow::Owned)?;
     let full_path = path_buf_from_cstr(value).map(Cow::Owned)?;
@@ -85,10 +108,156 @@ pub(crate) fn cstr_entry_to_crate_entry<'cache>(
     Ok(crate::Entry {
         file_name,
         full_path,
+        metadata,
+        cache_format,
+        cache_path: Cow::Borrowed(cache_path),
     })
 }
 
-pub(crate) fn map_file(path: &Path) -> Result<Mmap> {
+/// Return the class of the ELF file at `path`, or `None` if it cannot be read as an ELF file.
+pub(crate) fn elf_class_of_file(path: &Path) -> Option<ElfClass> {
+    const ELF_CLASS_32: u8 = 1;
+    const ELF_CLASS_64: u8 = 2;
+
+    let mut ident = [0_u8; 5];
+    File::open(path).ok()?.read_exact(&mut ident).ok()?;
+
+    match ident {
+        [0x7f, b'E', b'L', b'F', ELF_CLASS_32] => Some(ElfClass::Elf32),
+        [0x7f, b'E', b'L', b'F', ELF_CLASS_64] => Some(ElfClass::Elf64),
+        _ => None,
+    }
+}
+
+/// Return the path on the host of `path`, interpreting `path` and the symbolic links it traverses
+/// relative to the directory `root`, similarly to `chroot`.
+///
+/// Components that do not exist are kept as they are.
+pub(crate) fn resolve_in_root(root: &Path, path: &Path) -> PathBuf {
+    const MAX_SYMBOLIC_LINKS: usize = 40;
+
+    let mut resolved = PathBuf::default();
+    let mut pending: Vec<_> = path.components().map(owned_component).rev().collect();
+    let mut symbolic_links = 0_usize;
+
+    while let Some(component) = pending.pop() {
+        match component.components().next() {
+            Some(Component::Prefix(_) | Component::RootDir) => resolved = PathBuf::default(),
+
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+
+            Some(Component::Normal(name)) => {
+                resolved.push(name);
+
+                if symbolic_links < MAX_SYMBOLIC_LINKS {
+                    if let Ok(target) = read_link(root.join(&resolved)) {
+                        symbolic_links = symbolic_links.saturating_add(1);
+                        resolved.pop();
+                        pending.extend(target.components().map(owned_component).rev());
+                    }
+                }
+            }
+
+            Some(Component::CurDir) | None => {}
+        }
+    }
+
+    root.join(resolved)
+}
+
+fn owned_component(component: Component<'_>) -> PathBuf {
+    PathBuf::from(component.as_os_str())
+}
+
+/// Contents of a cache, either mapped from a file, or owned in memory.
+pub(crate) enum CacheBytes {
+    Map(Mmap),
+    Owned(Box<dyn AsRef<[u8]> + Send + Sync>),
+}
+
+impl CacheBytes {
+    pub(crate) fn from_owner(bytes: impl AsRef<[u8]> + Send + Sync + 'static) -> Self {
+        Self::Owned(Box::new(bytes))
+    }
+}
+
+impl Deref for CacheBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Map(map) => map,
+            Self::Owned(bytes) => (**bytes).as_ref(),
+        }
+    }
+}
+
+impl fmt::Debug for CacheBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Map(map) => f.debug_tuple("Map").field(map).finish(),
+            Self::Owned(bytes) => f
+                .debug_struct("Owned")
+                .field("len", &(**bytes).as_ref().len())
+                .finish(),
+        }
+    }
+}
+
+/// Version of a file, identifying the contents of the file at some point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileVersion {
+    device: u64,
+    inode: u64,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileVersion {
+    /// Return the current version of the file at `path`, or `None` if it cannot be accessed.
+    pub(crate) fn of_path(path: &Path) -> Option<Self> {
+        std::fs::metadata(path).ok().map(|md| Self::new(&md))
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn new(md: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        Self {
+            device: md.dev(),
+            inode: md.ino(),
+            size: md.len(),
+            modified: md.modified().ok(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn new(md: &Metadata) -> Self {
+        Self {
+            device: 0,
+            inode: 0,
+            size: md.len(),
+            modified: md.modified().ok(),
+        }
+    }
+}
+
+pub(crate) fn map_file(path: &Path) -> Result<CacheBytes> {
+    load_file(path, LoadStrategy::Map, Ok, |_| Ok(()))
+}
+
+/// Load the file at `path` according to `strategy`, then parse its contents with `parse`.
+///
+/// If `strategy` is [`LoadStrategy::MapVerified`], then `validate` decodes all the data that
+/// the parsed contents might access later, before the file is verified to be unchanged.
+pub(crate) fn load_file<T>(
+    path: &Path,
+    strategy: LoadStrategy,
+    parse: impl FnOnce(CacheBytes) -> Result<T>,
+    validate: impl FnOnce(&T) -> Result<()>,
+) -> Result<T> {
     let file = File::open(path).map_err(|source| Error::Open {
         source,
         path: path.into(),
@@ -99,13 +268,54 @@ pub(crate) fn map_file(path: &Path) -> Result<Mmap> {
         path: path.into(),
     })?;
 
-    let size = usize::try_from(md.len()).unwrap_or(usize::MAX);
+    let size = usize::try_from(md.len()).map_err(|_| Error::FileIsTooLarge {
+        path: path.into(),
+        size: md.len(),
+    })?;
     if size == 0 {
         return Err(Error::FileIsEmpty { path: path.into() });
     }
 
-    unsafe { MmapOptions::default().len(size).map(&file) }.map_err(|source| Error::MapFile {
-        source,
-        path: path.into(),
-    })
+    match strategy {
+        LoadStrategy::Map => parse(map_opened_file(path, &file, size)?),
+
+        LoadStrategy::MapVerified => {
+            let result = parse(map_opened_file(path, &file, size)?)?;
+            validate(&result)?;
+
+            // The mapped file must not be resized, and `path` must still name it.
+            let version = FileVersion::new(&md);
+            let is_unchanged = [file.metadata(), std::fs::metadata(path)]
+                .into_iter()
+                .all(|md| md.is_ok_and(|md| FileVersion::new(&md) == version));
+
+            if is_unchanged {
+                Ok(result)
+            } else {
+                Err(Error::FileChanged { path: path.into() })
+            }
+        }
+
+        LoadStrategy::Read => {
+            let mut bytes = Vec::new();
+            bytes
+                .try_reserve_exact(size)
+                .map_err(io::Error::from)
+                .and_then(|()| (&file).read_to_end(&mut bytes))
+                .map_err(|source| Error::Read {
+                    source,
+                    path: path.into(),
+                })?;
+            parse(CacheBytes::from_owner(bytes))
+        }
+    }
+}
+
+fn map_opened_file(path: &Path, file: &File, size: usize) -> Result<CacheBytes> {
+    unsafe { MmapOptions::default().len(size).map(file) }
+        .map(CacheBytes::Map)
+        .map_err(|source| Error::MapFile {
+            source,
+            path: path.into(),
+        })
 }
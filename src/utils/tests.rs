@@ -0,0 +1,80 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use assert_matches::assert_matches;
+
+use super::{load_file, resolve_in_root};
+use crate::{Error, LoadStrategy};
+
+#[test]
+fn load_file_strategies() {
+    let path = std::env::temp_dir().join(format!(
+        "dynamic-loader-cache-load-file-{}",
+        std::process::id()
+    ));
+
+    for strategy in [
+        LoadStrategy::Map,
+        LoadStrategy::MapVerified,
+        LoadStrategy::Read,
+    ] {
+        fs::write(&path, b"contents").unwrap();
+
+        let contents = load_file(&path, strategy, |bytes| Ok(bytes.to_vec()), |_| Ok(())).unwrap();
+        assert_eq!(contents, b"contents");
+
+        let result = load_file(
+            &path,
+            strategy,
+            |bytes| Ok(bytes.to_vec()),
+            |_| {
+                // Modify the file while its contents are validated.
+                let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+                file.write_all(b" and more").unwrap();
+                Ok(())
+            },
+        );
+
+        if strategy == LoadStrategy::MapVerified {
+            assert_matches!(result, Err(Error::FileChanged { .. }));
+        } else {
+            assert_eq!(result.unwrap(), b"contents");
+        }
+    }
+
+    fs::write(&path, b"").unwrap();
+    assert_matches!(
+        load_file(&path, LoadStrategy::Read, |_| Ok(()), |_| Ok(())),
+        Err(Error::FileIsEmpty { .. })
+    );
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn resolve_in_root_symbolic_links() {
+    let root = Path::new("tests/root");
+
+    // `/lib` is a symbolic link to `/fixture/lib`, which only exists under the root directory.
+    assert_eq!(
+        resolve_in_root(root, Path::new("/lib/libc.so.7")),
+        root.join("fixture/lib/libc.so.7")
+    );
+    assert_eq!(
+        resolve_in_root(root, Path::new("/var/../lib/./libc.so.7")),
+        root.join("fixture/lib/libc.so.7")
+    );
+
+    // Parent directories do not escape the root directory.
+    assert_eq!(
+        resolve_in_root(root, Path::new("/../../etc/ld.so.cache")),
+        root.join("etc/ld.so.cache")
+    );
+
+    // Missing components are kept as they are.
+    assert_eq!(
+        resolve_in_root(root, Path::new("/missing/file")),
+        root.join("missing/file")
+    );
+}
@@ -0,0 +1,371 @@
+// Copyright 2024 Koutheir Attouchi.
+// See the "LICENSE.txt" file at the top-level directory of this distribution.
+//
+// Licensed under the MIT license. This file may not be copied, modified,
+// or distributed except according to those terms.
+
+//! Watcher of the dynamic loader caches, which reloads them when they change.
+//!
+//! This module is only available on Linux, when the `watch` feature is enabled.
+//!
+//! The cache files are not watched directly. Instead, their parent directories are watched,
+//! because `ldconfig` replaces the cache files by renaming new files over them.
+//! The directories searched by `ld-elf.so.hints` caches are also watched, because the entries
+//! of these caches are the shared libraries present in these directories.
+//!
+//! Changes are debounced: a new snapshot of the cache is only delivered once no changes
+//! happened during the debounce delay, so that a cache that is rewritten in several steps
+//! is reloaded once.
+
+#[cfg(test)]
+mod tests;
+
+use core::mem::size_of;
+use core::ptr;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{AnyCache, Cache, Error, Result};
+
+/// Default delay during which no changes must happen before the caches are reloaded.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Maximum number of debounce delays to wait for, when changes happen continuously.
+const MAX_DEBOUNCE_DELAYS: u32 = 10;
+
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MODIFY
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_ATTRIB
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF
+    | libc::IN_ONLYDIR;
+
+/// Watcher of the dynamic loader caches.
+///
+/// The caches are watched by a background thread, which delivers new snapshots of the caches
+/// when they change. The thread is stopped when the watcher is dropped.
+#[derive(Debug)]
+pub struct Watcher {
+    stop: OwnedFd,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Watch the cache files of `cache`, and call `on_change` with a new snapshot of the caches
+    /// every time they change.
+    ///
+    /// Only the cache files that changed are loaded again, as with [`Cache::reload()`].
+    /// If reloading fails, then `on_change` receives the failure, and the caches are reloaded
+    /// again at the next change.
+    ///
+    /// `on_change` is called by a background thread, once no changes happened during
+    /// `debounce`. See [`DEFAULT_DEBOUNCE`].
+    pub fn new<F>(cache: Cache, debounce: Duration, on_change: F) -> Result<Self>
+    where
+        F: FnMut(Result<Cache>) + Send + 'static,
+    {
+        // Watch the directories before returning, so that no changes are missed.
+        let mut inotify = Inotify::new()?;
+        inotify.update_watches(&cache);
+
+        let stop = new_event_fd()?;
+        let stop_fd = stop.try_clone().map_err(|source| Error::Watch { source })?;
+
+        let thread = thread::Builder::new()
+            .name("dynamic-loader-cache-watch".into())
+            .spawn(move || watch(cache, inotify, &stop_fd, debounce, on_change))
+            .map_err(|source| Error::Watch { source })?;
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Watch the cache files of `cache`, and send a new snapshot of the caches to the returned
+    /// channel every time they change.
+    ///
+    /// See [`Watcher::new()`].
+    pub fn with_channel(
+        cache: Cache,
+        debounce: Duration,
+    ) -> Result<(Self, mpsc::Receiver<Result<Cache>>)> {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = Self::new(cache, debounce, move |snapshot| {
+            // The receiver might have been dropped before the watcher.
+            let _ignored = sender.send(snapshot);
+        })?;
+        Ok((watcher, receiver))
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let value = 1_u64;
+        // SAFETY: `self.stop` is a valid event file descriptor, and `value` is 8 bytes long.
+        let _ignored = unsafe {
+            libc::write(
+                self.stop.as_raw_fd(),
+                ptr::addr_of!(value).cast(),
+                size_of::<u64>(),
+            )
+        };
+
+        if let Some(thread) = self.thread.take() {
+            let _ignored = thread.join();
+        }
+    }
+}
+
+/// Result of waiting for changes.
+enum Wait {
+    Stopped,
+    TimedOut,
+    Changed { libraries: bool },
+}
+
+fn watch(
+    mut cache: Cache,
+    mut inotify: Inotify,
+    stop: &OwnedFd,
+    debounce: Duration,
+    mut on_change: impl FnMut(Result<Cache>),
+) {
+    loop {
+        let mut libraries_changed = match inotify.wait(stop, None) {
+            Ok(Wait::Changed { libraries }) => libraries,
+            Ok(Wait::TimedOut) => continue,
+            Ok(Wait::Stopped) => return,
+
+            Err(err) => {
+                on_change(Err(err));
+                return;
+            }
+        };
+
+        // Wait until no changes happen during the debounce delay.
+        let deadline = Instant::now() + debounce.saturating_mul(MAX_DEBOUNCE_DELAYS);
+        while Instant::now() < deadline {
+            match inotify.wait(stop, Some(debounce)) {
+                Ok(Wait::Changed { libraries }) => libraries_changed |= libraries,
+                Ok(Wait::TimedOut) => break,
+                Ok(Wait::Stopped) => return,
+
+                Err(err) => {
+                    on_change(Err(err));
+                    return;
+                }
+            }
+        }
+
+        if cache.is_stale() {
+            match cache.reload() {
+                Ok(new_cache) => {
+                    cache = new_cache;
+                    on_change(Ok(cache.clone()));
+                }
+
+                Err(err) => on_change(Err(err)),
+            }
+        } else if libraries_changed {
+            on_change(Ok(cache.clone()));
+        }
+
+        inotify.update_watches(&cache);
+    }
+}
+
+/// Instance of `inotify`, and the directories it watches.
+struct Inotify {
+    fd: OwnedFd,
+    watches: HashMap<PathBuf, libc::c_int>,
+    /// Watches of directories of shared libraries, or of their ancestors.
+    library_watches: HashSet<libc::c_int>,
+}
+
+impl Inotify {
+    fn new() -> Result<Self> {
+        // SAFETY: No pointers are involved.
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd == -1_i32 {
+            let source = io::Error::last_os_error();
+            return Err(Error::Watch { source });
+        }
+
+        Ok(Self {
+            // SAFETY: `fd` is a valid file descriptor owned by nothing else.
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            watches: HashMap::default(),
+            library_watches: HashSet::default(),
+        })
+    }
+
+    /// Watch the directories of the cache files of `cache`, and the directories searched by its
+    /// `ld-elf.so.hints` caches, and stop watching other directories.
+    ///
+    /// Directories that do not exist are not watched, but their nearest existing ancestors are,
+    /// in order to detect their creation.
+    fn update_watches(&mut self, cache: &Cache) {
+        let mut directories = HashMap::<PathBuf, bool>::default();
+
+        for file in &cache.files {
+            if let Some(dir) = file.path.parent().and_then(existing_ancestor) {
+                directories.entry(dir).or_default();
+            }
+        }
+
+        for cache in cache.caches() {
+            let AnyCache::LdELFSOHints(cache) = cache else {
+                continue;
+            };
+
+            let dirs = cache.directories().into_iter().flatten().flatten();
+            for dir in dirs {
+                if let Some(dir) = existing_ancestor(&cache.host_path(&dir)) {
+                    *directories.entry(dir).or_default() = true;
+                }
+            }
+        }
+
+        // Watching a directory again returns the same watch descriptor, unless the directory
+        // was replaced, so all watches are added again.
+        let mut watches = HashMap::with_capacity(directories.len());
+        self.library_watches.clear();
+        for (dir, is_library_dir) in directories {
+            if let Some(wd) = self.add_watch(&dir) {
+                watches.insert(dir, wd);
+                if is_library_dir {
+                    self.library_watches.insert(wd);
+                }
+            }
+        }
+
+        // Directories might be aliased by symbolic links, so only remove unused watch descriptors.
+        let used: HashSet<_> = watches.values().copied().collect();
+        for wd in self.watches.values().filter(|wd| !used.contains(wd)) {
+            // SAFETY: No pointers are involved.
+            // This fails if the directory was removed, which already removed the watch.
+            let _ignored = unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), *wd) };
+        }
+
+        self.watches = watches;
+    }
+
+    fn add_watch(&self, dir: &Path) -> Option<libc::c_int> {
+        let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+        // SAFETY: `dir` is a valid C string.
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), dir.as_ptr(), WATCH_MASK) };
+        (wd != -1_i32).then_some(wd)
+    }
+
+    /// Wait for changes during `timeout`, or indefinitely, until `stop` is signaled.
+    fn wait(&self, stop: &OwnedFd, timeout: Option<Duration>) -> Result<Wait> {
+        let mut fds = [
+            libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: stop.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+
+        let timeout = timeout.map_or(-1_i32, |timeout| {
+            libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX)
+        });
+
+        // SAFETY: `fds` is an array of `fds.len()` elements.
+        let count = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        if count == -1_i32 {
+            let source = io::Error::last_os_error();
+            return if source.kind() == io::ErrorKind::Interrupted {
+                Ok(Wait::TimedOut)
+            } else {
+                Err(Error::Watch { source })
+            };
+        }
+
+        if fds[1].revents != 0 {
+            Ok(Wait::Stopped)
+        } else if fds[0].revents != 0 {
+            self.read_events()
+        } else {
+            Ok(Wait::TimedOut)
+        }
+    }
+
+    /// Read all pending events, and tell whether directories of shared libraries changed.
+    fn read_events(&self) -> Result<Wait> {
+        let mut buffer = [0_u8; 4096];
+        let mut libraries = false;
+
+        loop {
+            // SAFETY: `buffer` is writable for `buffer.len()` bytes.
+            let size = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            };
+
+            if size == -1_isize {
+                let source = io::Error::last_os_error();
+                return match source.kind() {
+                    io::ErrorKind::WouldBlock => Ok(Wait::Changed { libraries }),
+                    io::ErrorKind::Interrupted => continue,
+                    _ => Err(Error::Watch { source }),
+                };
+            }
+
+            let bytes = &buffer[..size as usize];
+            let mut offset = 0_usize;
+            while offset.saturating_add(size_of::<libc::inotify_event>()) <= bytes.len() {
+                // SAFETY: `bytes` holds a complete event at `offset`, which might be unaligned.
+                let event: libc::inotify_event =
+                    unsafe { ptr::read_unaligned(bytes[offset..].as_ptr().cast()) };
+
+                // Events might have been lost, so shared libraries might have changed.
+                libraries |= (event.mask & libc::IN_Q_OVERFLOW) != 0
+                    || self.library_watches.contains(&event.wd);
+
+                offset = offset
+                    .saturating_add(size_of::<libc::inotify_event>())
+                    .saturating_add(event.len as usize);
+            }
+        }
+    }
+}
+
+/// Return `path`, or its nearest ancestor, that is an existing directory.
+fn existing_ancestor(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|path| path.is_dir())
+        .map(Path::to_path_buf)
+}
+
+fn new_event_fd() -> Result<OwnedFd> {
+    // SAFETY: No pointers are involved.
+    let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+    if fd == -1_i32 {
+        let source = io::Error::last_os_error();
+        Err(Error::Watch { source })
+    } else {
+        // SAFETY: `fd` is a valid file descriptor owned by nothing else.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::time::Duration;
+
+use super::Watcher;
+use crate::{glibc_ld_so_cache_1dot1, Cache, CacheBuilder, CacheFormat};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn watcher() {
+    let root =
+        std::env::temp_dir().join(format!("dynamic-loader-cache-watch-{}", std::process::id()));
+    fs::create_dir_all(root.join("etc")).unwrap();
+    fs::create_dir_all(root.join("var/run")).unwrap();
+    fs::create_dir_all(root.join("lib")).unwrap();
+    fs::copy(
+        "tests/glibc-ld.so.cache1.1/ld.so.cache",
+        root.join("etc/ld.so.cache"),
+    )
+    .unwrap();
+    fs::copy(
+        "tests/root/var/run/ld-elf.so.hints",
+        root.join("var/run/ld-elf.so.hints"),
+    )
+    .unwrap();
+
+    let cache = CacheBuilder::new()
+        .root(&root)
+        .skip_invalid_files(false)
+        .load()
+        .unwrap();
+    let (watcher, receiver) = Watcher::with_channel(cache, Duration::from_millis(20)).unwrap();
+
+    // Replace the cache file the way `ldconfig` does.
+    let new_path = root.join("etc/ld.so.cache~");
+    fs::copy("tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps", &new_path).unwrap();
+    fs::rename(&new_path, root.join("etc/ld.so.cache")).unwrap();
+
+    let cache = receiver.recv_timeout(TIMEOUT).unwrap().unwrap();
+    assert!(!cache.is_stale());
+    let expected =
+        glibc_ld_so_cache_1dot1::Cache::load("tests/glibc-ld.so.cache1.1/ld.so.cache.hwcaps")
+            .unwrap();
+    let glibc_entries = cache
+        .iter()
+        .unwrap()
+        .map(Result::unwrap)
+        .filter(|entry| entry.cache_format == CacheFormat::GLibCLdSOCache1dot1);
+    assert_eq!(glibc_entries.count(), expected.iter().unwrap().count());
+
+    // Add a shared library to a directory searched by the `ld-elf.so.hints` cache.
+    assert!(cache.lookup_first("libm.so.5").unwrap().is_none());
+    fs::copy(
+        "tests/root/fixture/lib/libc.so.7",
+        root.join("lib/libm.so.5"),
+    )
+    .unwrap();
+
+    let cache: Cache = receiver.recv_timeout(TIMEOUT).unwrap().unwrap();
+    assert!(cache.lookup_first("libm.so.5").unwrap().is_some());
+
+    // Dropping the watcher stops it.
+    drop(watcher);
+    assert!(receiver.recv().is_err());
+
+    fs::remove_dir_all(&root).unwrap();
+}